@@ -9,8 +9,10 @@ use std::time::Duration;
 use ultimate64::vicstream::{self, capture_frame, get_socket};
 use url::Url;
 
+type SharedFrame = Arc<Mutex<Option<ImageBuffer<Rgb<u8>, Vec<u8>>>>>;
+
 struct VideoApp {
-    latest_frame: Arc<Mutex<Option<ImageBuffer<Rgb<u8>, Vec<u8>>>>>,
+    latest_frame: SharedFrame,
     texture_handle: Option<egui::TextureHandle>,
 }
 
@@ -0,0 +1,638 @@
+//! # Asynchronous REST client
+//!
+//! Available with the `async` cargo feature. Sends the same requests as
+//! [`Rest`](crate::Rest), but without blocking the calling task.
+
+use crate::{
+    auxiliary::{check_address_overflow, check_data_overflow},
+    capabilities::{Capabilities, Feature},
+    config::{
+        self, CategoryList, ConfigCategory, ConfigItem, ConfigResponse, ConfigValue, Configuration,
+    },
+    drives::{DiskImageType, Drive, DriveId, DriveList, DriveType, MountMode},
+    request::{self, Payload, Request, Target},
+    snapshot::MachineSnapshot,
+    DeviceInfo, RestBuilder, Result, StreamType,
+};
+use log::{debug, warn};
+use reqwest::{multipart, Client, Response};
+use std::{collections::HashMap, path::Path, sync::OnceLock, time::Duration};
+use tokio::time::{sleep, Instant};
+use url::Host;
+
+/// Asynchronous communication with Ultimate series using
+/// the [REST API](https://1541u-documentation.readthedocs.io/en/latest/api/api_calls.html)
+///
+/// # Examples
+/// ~~~ rust, ignore
+/// use ultimate64::AsyncRest;
+/// let ultimate = AsyncRest::new(&Host::parse("192.168.1.10")?, None)?;
+/// ultimate.reset().await?;
+/// ~~~
+#[derive(Debug, Clone)]
+pub struct AsyncRest {
+    /// HTTP client
+    client: Client,
+    /// URL prefix and headers
+    target: Target,
+    /// Capabilities, probed on first use
+    capabilities: OnceLock<Capabilities>,
+}
+
+impl AsyncRest {
+    /// Create new AsyncRest instance
+    pub fn new(host: &Host, password: Option<String>) -> Result<Self> {
+        RestBuilder::new(host).password(password).build_async()
+    }
+
+    /// Create new AsyncRest instance for a device listening on a non-default port
+    pub fn with_port(host: &Host, port: u16, password: Option<String>) -> Result<Self> {
+        RestBuilder::new(host)
+            .port(port)
+            .password(password)
+            .build_async()
+    }
+
+    /// Client and target prepared by [`RestBuilder`]
+    pub(crate) fn from_parts(client: Client, target: Target) -> Self {
+        Self {
+            client,
+            target,
+            capabilities: OnceLock::new(),
+        }
+    }
+
+    /// Send request, retrying idempotent requests on network errors if enabled
+    async fn send(&self, request: Request) -> Result<Response> {
+        let retry = self.target.retry;
+        if !retry.applies_to(&request) {
+            return self.send_once(request).await;
+        }
+        let mut attempt = 0;
+        loop {
+            match self.send_once(request.clone()).await {
+                Err(error) => match retry.delay(attempt, &error) {
+                    Some(delay) => {
+                        warn!("{error}; retrying in {delay:?}");
+                        sleep(delay).await;
+                        attempt += 1;
+                    }
+                    None => return Err(error),
+                },
+                result => return result,
+            }
+        }
+    }
+
+    /// Send request once and check sanity of response
+    async fn send_once(&self, request: Request) -> Result<Response> {
+        let builder = self
+            .client
+            .request(request.method.clone(), self.target.url(&request))
+            .headers(self.target.headers.clone());
+        let builder = match request.payload {
+            Payload::Empty => builder,
+            Payload::Bytes(data) => builder.body(data),
+            Payload::Json(data) => builder
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(data),
+            Payload::Form {
+                file_name,
+                data,
+                fields,
+            } => {
+                let part = multipart::Part::bytes(data).file_name(file_name);
+                let form = fields
+                    .into_iter()
+                    .fold(multipart::Form::new().part("file", part), |form, (k, v)| {
+                        form.text(k, v)
+                    });
+                builder.multipart(form)
+            }
+        };
+        let response = builder.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            Ok(response)
+        } else {
+            Err(request::status_error(
+                status,
+                &response.text().await.unwrap_or_default(),
+            ))
+        }
+    }
+
+    /// Send request and discard response
+    async fn execute(&self, request: Request) -> Result<()> {
+        self.send(request).await?;
+        Ok(())
+    }
+
+    /// Products and firmware features of the device, probed once and cached
+    pub async fn capabilities(&self) -> Result<&Capabilities> {
+        if let Some(capabilities) = self.capabilities.get() {
+            return Ok(capabilities);
+        }
+        let version = self.send(request::version()).await?.json().await?;
+        let capabilities = request::decode_capabilities(version, self.info().await)?;
+        Ok(self.capabilities.get_or_init(|| capabilities))
+    }
+
+    /// Fail with [`Error::Incompatible`](crate::Error::Incompatible) unless the device supports `feature`
+    pub async fn require(&self, feature: Feature) -> Result<()> {
+        self.capabilities().await?.require(feature)
+    }
+
+    /// Get device information
+    pub async fn info(&self) -> Result<DeviceInfo> {
+        let body = self.send(request::info()).await?.text().await?;
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    /// Get version
+    pub async fn version(&self) -> Result<String> {
+        Ok(self.send(request::version()).await?.text().await?)
+    }
+
+    /// Get drives
+    pub async fn drives(&self) -> Result<String> {
+        Ok(self.send(request::drives()).await?.text().await?)
+    }
+
+    /// Load PRG bytes into memory - do NOT run.
+    pub async fn load_prg(&self, prg_data: &[u8]) -> Result<()> {
+        self.execute(request::load_prg(prg_data)).await
+    }
+
+    /// Load and run PRG bytes into memory
+    pub async fn run_prg(&self, data: &[u8]) -> Result<()> {
+        self.execute(request::run_prg(data)).await
+    }
+
+    /// Start supplied cartridge file
+    pub async fn run_crt(&self, data: &[u8]) -> Result<()> {
+        self.execute(request::run_crt(data)).await
+    }
+
+    /// Load PRG file stored on the Ultimate's USB or SD storage - do NOT run.
+    pub async fn load_remote_prg(&self, path: &str) -> Result<()> {
+        self.execute(request::load_remote_prg(path)).await
+    }
+
+    /// Load and run PRG file stored on the Ultimate's USB or SD storage
+    pub async fn run_remote_prg(&self, path: &str) -> Result<()> {
+        self.execute(request::run_remote_prg(path)).await
+    }
+
+    /// Start cartridge file stored on the Ultimate's USB or SD storage
+    pub async fn run_remote_crt(&self, path: &str) -> Result<()> {
+        self.execute(request::run_remote_crt(path)).await
+    }
+
+    /// Emulate pressing the menu button
+    pub async fn menu(&self) -> Result<()> {
+        self.execute(request::menu()).await
+    }
+
+    /// Reset machine
+    pub async fn reset(&self) -> Result<()> {
+        self.execute(request::reset()).await
+    }
+
+    /// Reboot machine
+    pub async fn reboot(&self) -> Result<()> {
+        self.execute(request::reboot()).await
+    }
+
+    /// Poll the device until it answers, e.g. after a reboot or power-on
+    ///
+    /// Fails with [`Error::Timeout`](crate::Error::Timeout) if the device is still unreachable after `timeout`.
+    pub async fn wait_online(&self, timeout: Duration) -> Result<()> {
+        let start = Instant::now();
+        loop {
+            match self.version().await {
+                Ok(_) => return Ok(()),
+                Err(error) if request::is_offline(&error) => {
+                    if start.elapsed() >= timeout {
+                        return Err(request::online_timeout(timeout, error));
+                    }
+                    debug!("Waiting for device: {error}");
+                }
+                Err(error) => return Err(error),
+            }
+            sleep(request::ONLINE_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Wait until the device is online and the C64 shows the BASIC READY prompt
+    ///
+    /// The prompt is detected in screen memory, so a screen showing `READY.`
+    /// from before a reset may be seen as ready.
+    pub async fn wait_ready(&self, timeout: Duration) -> Result<()> {
+        let start = Instant::now();
+        self.wait_online(timeout).await?;
+        let (address, length) = request::CURSOR_STATE;
+        loop {
+            let cursor_state = self.read_mem(address, length).await?;
+            let screen = self
+                .read_mem(request::SCREEN_ADDRESS, request::SCREEN_SIZE)
+                .await?;
+            if request::screen_ready(&cursor_state, &screen) {
+                return Ok(());
+            }
+            if start.elapsed() >= timeout {
+                return Err(request::ready_timeout(timeout));
+            }
+            sleep(request::ONLINE_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Pause machine
+    pub async fn pause(&self) -> Result<()> {
+        self.execute(request::pause()).await
+    }
+
+    /// Resume machine
+    pub async fn resume(&self) -> Result<()> {
+        self.execute(request::resume()).await
+    }
+
+    /// Pause, capture RAM and I/O chip registers, and resume
+    pub async fn snapshot(&self) -> Result<MachineSnapshot> {
+        self.pause().await?;
+        let data: Result<Vec<_>> = async {
+            let mut data = Vec::new();
+            for (address, length) in MachineSnapshot::reads() {
+                data.push(self.read_mem(address, length).await?);
+            }
+            Ok(data)
+        }
+        .await;
+        self.resume().await?;
+        MachineSnapshot::from_reads(data?)
+    }
+
+    /// Pause, write back a snapshot, and resume
+    pub async fn restore_snapshot(&self, snapshot: &MachineSnapshot) -> Result<()> {
+        let writes = snapshot.writes()?;
+        self.pause().await?;
+        let result: Result<()> = async {
+            for (address, data) in writes {
+                self.write_mem(address, &data).await?;
+            }
+            Ok(())
+        }
+        .await;
+        self.resume().await?;
+        result
+    }
+
+    /// Poweroff machine
+    pub async fn poweroff(&self) -> Result<()> {
+        self.execute(request::poweroff()).await
+    }
+
+    /// Read the U64 debug register ($D7FF)
+    pub async fn read_debugreg(&self) -> Result<u8> {
+        request::decode_debugreg(self.send(request::read_debugreg()).await?.json().await?)
+    }
+
+    /// Write to the U64 debug register ($D7FF)
+    pub async fn write_debugreg(&self, value: u8) -> Result<()> {
+        self.execute(request::write_debugreg(value)).await
+    }
+
+    /// Poll the debug register until the C64 program writes `value`
+    pub async fn wait_for_debugreg(&self, value: u8, timeout: Duration) -> Result<()> {
+        let start = Instant::now();
+        loop {
+            let current = self.read_debugreg().await?;
+            if current == value {
+                return Ok(());
+            }
+            if start.elapsed() >= timeout {
+                return Err(request::debugreg_timeout(value, current));
+            }
+            sleep(request::DEBUGREG_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Write data to memory using a POST request
+    pub async fn write_mem(&self, address: u16, data: &[u8]) -> Result<()> {
+        self.write_mem_with_progress(address, data, |_, _| {}).await
+    }
+
+    /// Write data to memory in chunks, calling `progress` with the number of bytes written so far and in total
+    ///
+    /// Chunks are sent one after another; the number of connections set on the builder only applies to [`Rest`](crate::Rest).
+    pub async fn write_mem_with_progress(
+        &self,
+        address: u16,
+        data: &[u8],
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<()> {
+        check_data_overflow(address, data.len())?;
+        let mut done = 0;
+        for (chunk_address, length) in self.target.transfer.chunks(address, data.len()) {
+            let chunk = &data[done..done + length as usize];
+            self.execute(request::write_mem(chunk_address, chunk)?)
+                .await?;
+            done += chunk.len();
+            progress(done, data.len());
+        }
+        Ok(())
+    }
+
+    /// Read `length` bytes from `address`
+    pub async fn read_mem(&self, address: u16, length: u16) -> Result<Vec<u8>> {
+        self.read_mem_with_progress(address, length, |_, _| {})
+            .await
+    }
+
+    /// Read memory in chunks, calling `progress` with the number of bytes read so far and in total
+    pub async fn read_mem_with_progress(
+        &self,
+        address: u16,
+        length: u16,
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<Vec<u8>> {
+        check_address_overflow(address, length)?;
+        let mut data = Vec::with_capacity(length as usize);
+        for (chunk_address, chunk_length) in self.target.transfer.chunks(address, length as usize) {
+            let bytes = self
+                .send(request::read_mem(chunk_address, chunk_length)?)
+                .await?
+                .bytes()
+                .await?;
+            request::check_read_length(chunk_address, chunk_length, &bytes)?;
+            data.extend_from_slice(&bytes);
+            progress(data.len(), length as usize);
+        }
+        Ok(data)
+    }
+
+    /// Read word (2 bytes) from memory and interpret as little endian
+    pub async fn read_le_word(&self, address: u16) -> Result<u16> {
+        request::decode_le_word(address, self.read_mem(address, 2).await?)
+    }
+
+    /// Emulate keyboard input
+    ///
+    /// Done by injecting PETSCII bytes to the C64 input buffer.
+    pub async fn type_text(&self, s: &str) -> Result<()> {
+        for chunk in request::type_text(s) {
+            for (address, data) in chunk {
+                self.write_mem(address, &data).await?;
+            }
+            sleep(request::TYPING_DELAY).await;
+        }
+        Ok(())
+    }
+
+    /// Load data into memory using either a custom address, or deduce the
+    /// load address from the first two bytes of the data (little endian).
+    /// Returns the load address and the number of bytes written.
+    pub async fn load_data(&self, data: &[u8], address: Option<u16>) -> Result<(u16, usize)> {
+        self.load_data_with_progress(data, address, |_, _| {}).await
+    }
+
+    /// Load data like [`load_data`](Self::load_data), calling `progress` with
+    /// the number of bytes written so far and in total
+    pub async fn load_data_with_progress(
+        &self,
+        data: &[u8],
+        address: Option<u16>,
+        progress: impl FnMut(usize, usize),
+    ) -> Result<(u16, usize)> {
+        let (address, payload) = request::load_data(data, address)?;
+        self.write_mem_with_progress(address, payload, progress)
+            .await?;
+        Ok((address, payload.len()))
+    }
+
+    /// Start a program loaded at `address` by typing `RUN` for BASIC at
+    /// $0801, or `SYS` with the address otherwise
+    pub async fn run_loaded(&self, address: u16) -> Result<()> {
+        self.type_text(&request::run_loaded_text(address)).await
+    }
+
+    /// Play SID file - if no `songnr` is provided, the default song is played.
+    pub async fn sid_play(&self, siddata: &[u8], songnr: Option<u8>) -> Result<()> {
+        self.execute(request::sid_play(siddata, songnr)).await
+    }
+
+    /// Play amiga MOD file
+    pub async fn mod_play(&self, moddata: &[u8]) -> Result<()> {
+        self.execute(request::mod_play(moddata)).await
+    }
+
+    /// Play SID file stored on the Ultimate's USB or SD storage
+    pub async fn sid_play_remote(&self, path: &str, songnr: Option<u8>) -> Result<()> {
+        self.execute(request::sid_play_remote(path, songnr)).await
+    }
+
+    /// Play amiga MOD file stored on the Ultimate's USB or SD storage
+    pub async fn mod_play_remote(&self, path: &str) -> Result<()> {
+        self.execute(request::mod_play_remote(path)).await
+    }
+
+    /// Get drive list
+    pub async fn drive_list(&self) -> Result<HashMap<String, Drive>> {
+        let nested: DriveList = self.send(request::drives()).await?.json().await?;
+        Ok(request::decode_drive_list(nested))
+    }
+
+    /// Get description of a single drive
+    pub async fn drive(&self, drive: DriveId) -> Result<Drive> {
+        request::find_drive(self.drive_list().await?, drive)
+    }
+
+    /// Mount local disk image
+    pub async fn mount_disk_image<P: AsRef<Path>>(
+        &self,
+        path: P,
+        drive: String,
+        mount_mode: MountMode,
+        run: bool,
+    ) -> Result<()> {
+        let path = path.as_ref();
+        DiskImageType::from_file_name(path)?;
+        // only the base name is sent; the device shows it in its file browser
+        let file_name = path
+            .file_name()
+            .map_or_else(|| path.to_string_lossy(), |name| name.to_string_lossy())
+            .to_string();
+        let data = tokio::fs::read(path).await?;
+        self.execute(request::mount_disk_image(
+            file_name, data, &drive, mount_mode,
+        )?)
+        .await?;
+        if run {
+            self.run_mounted_disk().await?;
+        }
+        Ok(())
+    }
+
+    /// Mount disk image already stored on the Ultimate's USB or SD storage
+    pub async fn mount_remote_image(
+        &self,
+        path: &str,
+        drive: String,
+        mount_mode: MountMode,
+        run: bool,
+    ) -> Result<()> {
+        self.execute(request::mount_remote_image(path, &drive, mount_mode)?)
+            .await?;
+        if run {
+            self.run_mounted_disk().await?;
+        }
+        Ok(())
+    }
+
+    /// Reset and run the first program on the mounted disk
+    async fn run_mounted_disk(&self) -> Result<()> {
+        self.reset().await?;
+        sleep(request::RESET_DELAY).await;
+        self.type_text(request::RUN_DISK_TEXT).await
+    }
+
+    /// Upload custom ROM image (e.g. JiffyDOS) to drive
+    pub async fn load_drive_rom(&self, drive: DriveId, rom: &[u8]) -> Result<Drive> {
+        let status = self.drive(drive).await?;
+        self.execute(request::load_drive_rom(drive, &status, rom)?)
+            .await?;
+        self.drive(drive).await
+    }
+
+    /// Turn drive on
+    pub async fn drive_on(&self, drive: DriveId) -> Result<()> {
+        self.execute(request::drive_on(drive)).await
+    }
+
+    /// Turn drive off
+    pub async fn drive_off(&self, drive: DriveId) -> Result<()> {
+        self.execute(request::drive_off(drive)).await
+    }
+
+    /// Reset drive
+    pub async fn drive_reset(&self, drive: DriveId) -> Result<()> {
+        self.execute(request::drive_reset(drive)).await
+    }
+
+    /// Eject disk image from drive
+    pub async fn drive_eject(&self, drive: DriveId) -> Result<()> {
+        self.execute(request::drive_eject(drive)).await
+    }
+
+    /// Unlink disk image from drive
+    pub async fn drive_unlink(&self, drive: DriveId) -> Result<()> {
+        self.execute(request::drive_unlink(drive)).await
+    }
+
+    /// Switch drive between 1541, 1571, and 1581 emulation
+    pub async fn drive_set_mode(&self, drive: DriveId, mode: DriveType) -> Result<()> {
+        self.execute(request::drive_set_mode(drive, mode)?).await
+    }
+
+    /// Create blank disk image on the Ultimate's USB or SD storage
+    pub async fn create_disk_image(
+        &self,
+        path: &str,
+        kind: DiskImageType,
+        name: Option<&str>,
+        tracks: Option<u8>,
+    ) -> Result<()> {
+        self.execute(request::create_disk_image(path, kind, name, tracks)?)
+            .await
+    }
+
+    /// Start video, audio, or debug streaming
+    pub async fn start_stream(&self, host: &Host, port: u16, kind: StreamType) -> Result<()> {
+        self.execute(request::start_stream(host, port, kind)).await
+    }
+
+    /// Stop video, audio, or debug streaming
+    pub async fn stop_stream(&self, kind: StreamType) -> Result<()> {
+        self.execute(request::stop_stream(kind)).await
+    }
+
+    /// List configuration categories
+    pub async fn config_categories(&self) -> Result<Vec<String>> {
+        let list: CategoryList = self
+            .send(request::config_categories())
+            .await?
+            .json()
+            .await?;
+        Ok(list.categories)
+    }
+
+    /// Get all items and their current values in a configuration category
+    pub async fn config_category(&self, category: &str) -> Result<ConfigCategory> {
+        let response: ConfigResponse<ConfigValue> = self
+            .send(request::config_category(category))
+            .await?
+            .json()
+            .await?;
+        request::decode_config_category(response, category)
+    }
+
+    /// Get a single configuration item including its allowed values or range
+    pub async fn config_item(&self, category: &str, item: &str) -> Result<ConfigItem> {
+        let response: ConfigResponse<ConfigItem> = self
+            .send(request::config_item(category, item))
+            .await?
+            .json()
+            .await?;
+        request::decode_config_item(response, category, item)
+    }
+
+    /// Set a single configuration item
+    pub async fn set_config_item(
+        &self,
+        category: &str,
+        item: &str,
+        value: &ConfigValue,
+    ) -> Result<()> {
+        self.execute(request::set_config_item(category, item, value))
+            .await
+    }
+
+    /// Set several configuration items in a single request
+    pub async fn set_config_items(&self, config: &Configuration) -> Result<()> {
+        self.execute(request::set_config_items(config)?).await
+    }
+
+    /// Get all configuration categories with their current values
+    pub async fn configuration(&self) -> Result<Configuration> {
+        let mut configuration = Configuration::new();
+        for category in self.config_categories().await? {
+            let items = self.config_category(&category).await?;
+            configuration.insert(category, items);
+        }
+        Ok(configuration)
+    }
+
+    /// Apply configuration, only sending items that differ from the device
+    pub async fn apply_configuration(&self, config: &Configuration) -> Result<Configuration> {
+        let changes = config::changed_items(&self.configuration().await?, config);
+        if !changes.is_empty() {
+            self.set_config_items(&changes).await?;
+        }
+        Ok(changes)
+    }
+
+    /// Save current configuration to flash so that it survives a power cycle
+    pub async fn save_config_to_flash(&self) -> Result<()> {
+        self.execute(request::save_config_to_flash()).await
+    }
+
+    /// Restore configuration from flash, discarding unsaved changes
+    pub async fn load_config_from_flash(&self) -> Result<()> {
+        self.execute(request::load_config_from_flash()).await
+    }
+
+    /// Reset configuration to factory defaults (not saved to flash)
+    pub async fn reset_config_to_defaults(&self) -> Result<()> {
+        self.execute(request::reset_config_to_defaults()).await
+    }
+}
@@ -2,8 +2,9 @@
 //! Auxiliary functions
 //!
 
-use anyhow::{anyhow, bail, Result};
-use std::{ffi::OsStr, path::Path};
+use crate::{Error, Result};
+use std::{ffi::OsStr, net::Ipv6Addr, path::Path, time::Duration};
+use url::Host;
 
 /// Check if 16-bit start address can contain `length` bytes
 ///
@@ -16,11 +17,32 @@ use std::{ffi::OsStr, path::Path};
 ///
 pub fn check_address_overflow(address: u16, length: u16) -> Result<()> {
     if length > 0 && u16::checked_add(address, length - 1).is_none() {
-        bail!(
+        Err(Error::invalid(format!(
             "Address {:#06x} + length {:#06x} overflows address space",
-            address,
-            length
-        )
+            address, length
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// Check if `length` bytes of data fit in memory from 16-bit start `address`
+///
+/// Unlike [`check_address_overflow`], the length may exceed 16 bits.
+///
+/// # Examples
+/// ```
+/// use ultimate64::auxiliary::check_data_overflow;
+/// assert!(check_data_overflow(0x0000, 0x10000).is_ok());
+/// assert!(check_data_overflow(0x0000, 0x10001).is_err());
+/// assert!(check_data_overflow(0x0801, 70000).is_err());
+/// ```
+pub fn check_data_overflow(address: u16, length: usize) -> Result<()> {
+    if address as usize + length > 0x10000 {
+        Err(Error::invalid(format!(
+            "Address {:#06x} + length {:#x} overflows address space",
+            address, length
+        )))
     } else {
         Ok(())
     }
@@ -56,7 +78,114 @@ pub fn get_extension<P: AsRef<Path>>(path: P) -> Option<String> {
 /// ```
 pub fn extract_load_address(data: &[u8]) -> Result<u16> {
     data.get(..2)
-        .ok_or_else(|| anyhow!("at least two bytes required to detect load address"))
+        .ok_or_else(|| Error::invalid("at least two bytes required to detect load address"))
         .map(|b| b.try_into().unwrap()) // -> [u8; 2] -  panic impossible
         .map(u16::from_le_bytes) // -> u16 using little-endian byte order
 }
+
+/// Percent-encode a string for use as a single URL path segment
+///
+/// Configuration categories and items contain spaces and other reserved
+/// characters, so they must be escaped before being inserted into a path.
+///
+/// # Examples
+/// ```
+/// use ultimate64::auxiliary::encode_path_segment;
+/// assert_eq!(encode_path_segment("Audio Mixer"), "Audio%20Mixer");
+/// assert_eq!(encode_path_segment("A/B"), "A%2FB");
+/// ```
+pub fn encode_path_segment(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// Percent-encode each segment of a `/` separated path on the device
+///
+/// # Examples
+/// ```
+/// use ultimate64::auxiliary::encode_device_path;
+/// assert_eq!(encode_device_path("/Usb0/my games/a.d64"), "Usb0/my%20games/a.d64");
+/// ```
+pub fn encode_device_path(path: &str) -> String {
+    path.trim_start_matches('/')
+        .split('/')
+        .map(encode_path_segment)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Address of the machine code started by a `SYS` in the first line of a tokenized BASIC program
+///
+/// `basic` is the program text as loaded at $0801, i.e. without load address.
+/// Returns `None` if the first line contains no `SYS` with a literal address.
+///
+/// # Examples
+/// ```
+/// use ultimate64::auxiliary::basic_sys_address;
+/// // 10 SYS 2061
+/// let basic = [0x0b, 0x08, 0x0a, 0x00, 0x9e, 0x20, 0x32, 0x30, 0x36, 0x31, 0x00, 0x00, 0x00];
+/// assert_eq!(basic_sys_address(&basic), Some(2061));
+/// assert_eq!(basic_sys_address(&[0x00, 0x00]), None);
+/// ```
+pub fn basic_sys_address(basic: &[u8]) -> Option<u16> {
+    const SYS_TOKEN: u8 = 0x9e;
+    let line = basic.get(4..)?; // skip link to next line and line number
+    let line = &line[..line.iter().position(|&b| b == 0)?];
+    let sys = line.iter().position(|&b| b == SYS_TOKEN)?;
+    let digits: String = line[sys + 1..]
+        .iter()
+        .skip_while(|&&b| b == b' ' || b == b'(')
+        .take_while(|b| b.is_ascii_digit())
+        .map(|&b| b as char)
+        .collect();
+    digits.parse().ok()
+}
+
+/// Parse hostname or IP address, also accepting IPv6 addresses without brackets
+///
+/// # Examples
+/// ```
+/// use ultimate64::auxiliary::parse_host;
+/// assert_eq!(parse_host("fe80::1").unwrap().to_string(), "[fe80::1]");
+/// assert_eq!(parse_host("[::1]").unwrap().to_string(), "[::1]");
+/// assert_eq!(parse_host("u64.local").unwrap().to_string(), "u64.local");
+/// ```
+pub fn parse_host(s: &str) -> Result<Host> {
+    match s.parse::<Ipv6Addr>() {
+        Ok(address) => Ok(Host::Ipv6(address)),
+        Err(_) => Host::parse(s).map_err(|e| Error::invalid(format!("invalid host {s}: {e}"))),
+    }
+}
+
+/// Parse duration such as `50ms`, `2s` or `0.5`; plain numbers are seconds
+///
+/// # Examples
+/// ```
+/// use std::time::Duration;
+/// use ultimate64::auxiliary::parse_duration;
+/// assert_eq!(parse_duration("50ms").unwrap(), Duration::from_millis(50));
+/// assert_eq!(parse_duration("1.5s").unwrap(), Duration::from_millis(1500));
+/// assert_eq!(parse_duration("2").unwrap(), Duration::from_secs(2));
+/// assert!(parse_duration("fast").is_err());
+/// ```
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let (number, scale) = if let Some(ms) = s.strip_suffix("ms") {
+        (ms, 1e-3)
+    } else {
+        (s.strip_suffix('s').unwrap_or(s), 1.0)
+    };
+    number
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .and_then(|value| Duration::try_from_secs_f64(value * scale).ok())
+        .ok_or_else(|| Error::invalid(format!("invalid duration {s}")))
+}
@@ -0,0 +1,266 @@
+//! # Backend abstraction
+//!
+//! The [`Ultimate`] trait describes what a device can do, so that tools can
+//! target the real hardware via [`Rest`](crate::Rest) as well as mocks,
+//! emulators, or proxies. Only memory access and reset must be implemented;
+//! other operations report [`Error::Unsupported`] unless overridden.
+
+use crate::{
+    drives::{Drive, DriveId, MountMode},
+    request, DeviceInfo, Error, Result,
+};
+use std::{
+    collections::HashMap,
+    path::Path,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+/// Operations supported by an Ultimate device or a stand-in for one
+pub trait Ultimate {
+    /// Read `length` bytes from `address`
+    fn read_mem(&self, address: u16, length: u16) -> Result<Vec<u8>>;
+
+    /// Write data to memory
+    fn write_mem(&self, address: u16, data: &[u8]) -> Result<()>;
+
+    /// Read memory, calling `progress` with the number of bytes read so far and in total
+    fn read_mem_with_progress(
+        &self,
+        address: u16,
+        length: u16,
+        progress: &mut dyn FnMut(usize, usize),
+    ) -> Result<Vec<u8>> {
+        let data = self.read_mem(address, length)?;
+        progress(data.len(), data.len());
+        Ok(data)
+    }
+
+    /// Write memory, calling `progress` with the number of bytes written so far and in total
+    fn write_mem_with_progress(
+        &self,
+        address: u16,
+        data: &[u8],
+        progress: &mut dyn FnMut(usize, usize),
+    ) -> Result<()> {
+        self.write_mem(address, data)?;
+        progress(data.len(), data.len());
+        Ok(())
+    }
+
+    /// Reset machine
+    fn reset(&self) -> Result<()>;
+
+    /// Get device information
+    fn info(&self) -> Result<DeviceInfo> {
+        Err(Error::Unsupported)
+    }
+
+    /// Get version
+    fn version(&self) -> Result<String> {
+        Err(Error::Unsupported)
+    }
+
+    /// Load PRG bytes into memory - do NOT run.
+    fn load_prg(&self, _data: &[u8]) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    /// Load and run PRG bytes
+    fn run_prg(&self, _data: &[u8]) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    /// Start supplied cartridge file
+    fn run_crt(&self, _data: &[u8]) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    /// Play SID file - if no `songnr` is provided, the default song is played.
+    fn sid_play(&self, _data: &[u8], _songnr: Option<u8>) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    /// Play amiga MOD file
+    fn mod_play(&self, _data: &[u8]) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    /// Emulate pressing the menu button
+    fn menu(&self) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    /// Reboot machine
+    fn reboot(&self) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    /// Pause machine
+    fn pause(&self) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    /// Resume machine
+    fn resume(&self) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    /// Poweroff machine
+    fn poweroff(&self) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    /// Read the U64 debug register ($D7FF)
+    fn read_debugreg(&self) -> Result<u8> {
+        Err(Error::Unsupported)
+    }
+
+    /// Write to the U64 debug register ($D7FF)
+    fn write_debugreg(&self, _value: u8) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    /// Get drive list
+    fn drive_list(&self) -> Result<HashMap<String, Drive>> {
+        Err(Error::Unsupported)
+    }
+
+    /// Mount local disk image, optionally resetting and running the first program
+    fn mount_disk_image(
+        &self,
+        _path: &Path,
+        _drive: String,
+        _mount_mode: MountMode,
+        _run: bool,
+    ) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    /// Get description of a single drive
+    fn drive(&self, drive: DriveId) -> Result<Drive> {
+        request::find_drive(self.drive_list()?, drive)
+    }
+
+    /// Read word (2 bytes) from memory and interpret as little endian
+    fn read_le_word(&self, address: u16) -> Result<u16> {
+        request::decode_le_word(address, self.read_mem(address, 2)?)
+    }
+
+    /// Emulate keyboard input
+    ///
+    /// Done by injecting PETSCII bytes to the C64 input buffer.
+    fn type_text(&self, s: &str) -> Result<()> {
+        for chunk in request::type_text(s) {
+            for (address, data) in chunk {
+                self.write_mem(address, &data)?;
+            }
+            sleep(request::TYPING_DELAY);
+        }
+        Ok(())
+    }
+
+    /// Load data into memory using either a custom address, or deduce the
+    /// load address from the first two bytes of the data (little endian).
+    /// In the case of the latter, the first two bytes are not written to memory.
+    /// Returns the load address and the number of bytes written.
+    fn load_data(&self, data: &[u8], address: Option<u16>) -> Result<(u16, usize)> {
+        self.load_data_with_progress(data, address, &mut |_, _| {})
+    }
+
+    /// Load data like [`load_data`](Self::load_data), calling `progress` with
+    /// the number of bytes written so far and in total
+    fn load_data_with_progress(
+        &self,
+        data: &[u8],
+        address: Option<u16>,
+        progress: &mut dyn FnMut(usize, usize),
+    ) -> Result<(u16, usize)> {
+        let (address, payload) = request::load_data(data, address)?;
+        self.write_mem_with_progress(address, payload, progress)?;
+        Ok((address, payload.len()))
+    }
+
+    /// Start a program loaded at `address` by typing `RUN` for BASIC at
+    /// $0801, or `SYS` with the address otherwise
+    fn run_loaded(&self, address: u16) -> Result<()> {
+        self.type_text(&request::run_loaded_text(address))
+    }
+
+    /// Reset and run the first program on the mounted disk
+    fn run_mounted_disk(&self) -> Result<()> {
+        // a short delay is needed to allow the reset to complete
+        self.reset()?;
+        sleep(request::RESET_DELAY);
+        self.type_text(request::RUN_DISK_TEXT)
+    }
+
+    /// Poll the debug register until the C64 program writes `value`
+    ///
+    /// Fails if the value has not appeared within `timeout`.
+    fn wait_for_debugreg(&self, value: u8, timeout: Duration) -> Result<()> {
+        let start = Instant::now();
+        loop {
+            let current = self.read_debugreg()?;
+            if current == value {
+                return Ok(());
+            }
+            if start.elapsed() >= timeout {
+                return Err(request::debugreg_timeout(value, current));
+            }
+            sleep(request::DEBUGREG_POLL_INTERVAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// Plain 64K RAM without any device behind it
+    struct Ram(RefCell<Vec<u8>>);
+
+    impl Ultimate for Ram {
+        fn read_mem(&self, address: u16, length: u16) -> Result<Vec<u8>> {
+            let start = address as usize;
+            Ok(self.0.borrow()[start..start + length as usize].to_vec())
+        }
+        fn write_mem(&self, address: u16, data: &[u8]) -> Result<()> {
+            let start = address as usize;
+            self.0.borrow_mut()[start..start + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+        fn reset(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_load_data() {
+        let ram = Ram(RefCell::new(vec![0; 0x10000]));
+        let (address, len) = ram.load_data(&[0x00, 0x10, 1, 2, 3], None).unwrap();
+        assert_eq!((address, len), (0x1000, 3));
+        assert_eq!(ram.read_mem(0x1000, 3).unwrap(), [1, 2, 3]);
+        assert_eq!(ram.read_le_word(0x1000).unwrap(), 0x0201);
+
+        let mut progress = Vec::new();
+        ram.load_data_with_progress(&[1, 2], Some(0x2000), &mut |done, total| {
+            progress.push((done, total))
+        })
+        .unwrap();
+        assert_eq!(progress, [(2, 2)]);
+    }
+
+    #[test]
+    fn test_type_text() {
+        let ram = Ram(RefCell::new(vec![0; 0x10000]));
+        ram.type_text("run\n").unwrap();
+        assert_eq!(ram.read_mem(0xc6, 1).unwrap(), [4]);
+        assert_eq!(ram.read_mem(0x277, 4).unwrap(), [0x52, 0x55, 0x4e, 0x0d]);
+        ram.run_loaded(0xc000).unwrap();
+        assert_eq!(ram.read_mem(0xc6, 1).unwrap(), [9]);
+        assert_eq!(ram.read_mem(0x277, 9).unwrap(), b"SYS49152\r");
+        assert!(matches!(ram.info(), Err(Error::Unsupported)));
+    }
+}
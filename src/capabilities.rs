@@ -0,0 +1,211 @@
+//! # Device capabilities
+//!
+//! Not every endpoint exists on every product and firmware. [`Capabilities`]
+//! are probed once from `/v1/version` and `/v1/info` so that unsupported
+//! commands can be refused before they are sent.
+
+use crate::{DeviceInfo, Error, Result};
+use core::fmt::Display;
+use std::str::FromStr;
+
+/// Product family
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Product {
+    /// Ultimate 64 and Ultimate 64 Elite boards
+    Ultimate64,
+    /// Ultimate-II, Ultimate-II+ and Ultimate-II+L cartridges
+    UltimateII,
+}
+
+impl Product {
+    /// Product family from the product name reported by `/v1/info`
+    ///
+    /// # Examples
+    /// ```
+    /// use ultimate64::capabilities::Product;
+    /// assert_eq!(Product::from_name("Ultimate 64 Elite"), Product::Ultimate64);
+    /// assert_eq!(Product::from_name("Ultimate-II+L"), Product::UltimateII);
+    /// ```
+    pub fn from_name(name: &str) -> Self {
+        if name.contains("64") {
+            Self::Ultimate64
+        } else {
+            Self::UltimateII
+        }
+    }
+}
+
+impl Display for Product {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ultimate64 => write!(f, "Ultimate 64"),
+            Self::UltimateII => write!(f, "Ultimate-II"),
+        }
+    }
+}
+
+/// Firmware version, e.g. `3.12` or `3.11b`; letter suffixes are ignored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Firmware {
+    /// Major version
+    pub major: u16,
+    /// Minor version
+    pub minor: u16,
+}
+
+impl Firmware {
+    /// New firmware version
+    pub const fn new(major: u16, minor: u16) -> Self {
+        Self { major, minor }
+    }
+}
+
+impl FromStr for Firmware {
+    type Err = Error;
+
+    /// # Examples
+    /// ```
+    /// use ultimate64::capabilities::Firmware;
+    /// let firmware: Firmware = "3.11b".parse().unwrap();
+    /// assert_eq!(firmware, Firmware::new(3, 11));
+    /// assert!(firmware < "3.12".parse().unwrap());
+    /// assert!("beta".parse::<Firmware>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::decode(format!("invalid firmware version: {s}"));
+        let (major, minor) = s.trim().split_once('.').ok_or_else(invalid)?;
+        let minor: String = minor.chars().take_while(char::is_ascii_digit).collect();
+        Ok(Self {
+            major: major.parse().map_err(|_| invalid())?,
+            minor: minor.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl Display for Firmware {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Groups of endpoints that are not available everywhere
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    /// U64 debug register (`machine:debugreg`)
+    DebugRegister,
+    /// VIC, audio and debug streams (`streams`)
+    Streams,
+    /// Powering off the machine (`machine:poweroff`)
+    Poweroff,
+    /// Creating blank disk images (`files/...:create_*`)
+    CreateDiskImage,
+    /// Changing drive type (`drives/...:set_mode`)
+    DriveMode,
+    /// Uploading custom drive ROMs (`drives/...:load_rom`)
+    DriveRom,
+}
+
+impl Feature {
+    /// Product the feature is limited to, if any
+    pub fn product(&self) -> Option<Product> {
+        match self {
+            Self::DebugRegister | Self::Streams | Self::Poweroff => Some(Product::Ultimate64),
+            Self::CreateDiskImage | Self::DriveMode | Self::DriveRom => None,
+        }
+    }
+
+    /// Firmware that introduced the endpoint
+    pub fn firmware(&self) -> Firmware {
+        // Versions from the firmware release notes at
+        // https://github.com/GideonZ/1541ultimate/releases and the REST API
+        // documentation at https://1541u-documentation.readthedocs.io/en/latest/api/api_calls.html
+        match self {
+            Self::DebugRegister | Self::Streams | Self::Poweroff => Firmware::new(3, 11),
+            Self::CreateDiskImage | Self::DriveMode | Self::DriveRom => Firmware::new(3, 12),
+        }
+    }
+}
+
+impl Display for Feature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::DebugRegister => "debug register",
+            Self::Streams => "streaming",
+            Self::Poweroff => "power off",
+            Self::CreateDiskImage => "disk image creation",
+            Self::DriveMode => "drive mode change",
+            Self::DriveRom => "drive ROM upload",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// What the connected device supports
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// REST API version from `/v1/version`
+    pub api_version: String,
+    /// Product family, if reported by the device
+    pub product: Option<Product>,
+    /// Firmware version, if reported by the device
+    pub firmware: Option<Firmware>,
+}
+
+impl Capabilities {
+    /// Capabilities from API version and optional device information
+    ///
+    /// Firmware without `/v1/info` leaves product and firmware unknown.
+    pub fn new(api_version: &str, info: Option<&DeviceInfo>) -> Self {
+        Self {
+            api_version: api_version.to_string(),
+            product: info.map(|info| Product::from_name(&info.product)),
+            firmware: info.and_then(|info| info.firmware_version.parse().ok()),
+        }
+    }
+
+    /// Whether `feature` is available; unknown product or firmware is given the benefit of the doubt
+    pub fn supports(&self, feature: Feature) -> bool {
+        let product_ok = match (feature.product(), self.product) {
+            (Some(required), Some(product)) => required == product,
+            _ => true,
+        };
+        let firmware_ok = match self.firmware {
+            Some(firmware) => firmware >= feature.firmware(),
+            None => true,
+        };
+        product_ok && firmware_ok
+    }
+
+    /// Fail with a message naming the required product and firmware unless `feature` is available
+    ///
+    /// # Examples
+    /// ```
+    /// use ultimate64::capabilities::{Capabilities, Feature};
+    /// let capabilities = Capabilities {
+    ///     api_version: "0.1".to_string(),
+    ///     product: Some(ultimate64::capabilities::Product::UltimateII),
+    ///     firmware: "3.11".parse().ok(),
+    /// };
+    /// assert!(capabilities.require(Feature::DriveRom).is_err());
+    /// assert!(capabilities.require(Feature::DebugRegister).is_err());
+    /// ```
+    pub fn require(&self, feature: Feature) -> Result<()> {
+        if self.supports(feature) {
+            return Ok(());
+        }
+        let product = feature
+            .product()
+            .map(|product| format!("{product} with "))
+            .unwrap_or_default();
+        let connected = match (self.product, self.firmware) {
+            (Some(p), Some(f)) => format!("{p} with firmware {f}"),
+            (Some(p), None) => p.to_string(),
+            (None, Some(f)) => format!("firmware {f}"),
+            (None, None) => "unknown device".to_string(),
+        };
+        Err(Error::Incompatible(format!(
+            "{feature} requires {product}firmware {} or newer (connected: {connected})",
+            feature.firmware()
+        )))
+    }
+}
@@ -0,0 +1,252 @@
+//! # Device configuration categories and items
+
+use crate::{auxiliary, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt::Display, path::Path};
+
+/// Value of a single configuration item
+///
+/// The Ultimate reports items either as integers (e.g. volume levels) or
+/// as one of a fixed set of strings (e.g. "Enabled" or "PAL").
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(untagged)]
+pub enum ConfigValue {
+    /// Integer value
+    Int(i64),
+    /// String value
+    Text(String),
+}
+
+impl ConfigValue {
+    /// Convert text to an integer if `reference`, e.g. the item's current value, is one
+    ///
+    /// Command line values are text as digits alone do not tell whether an
+    /// item is numeric; drive types such as "1541" are strings.
+    ///
+    /// # Examples
+    /// ```
+    /// use ultimate64::config::ConfigValue;
+    /// let value = ConfigValue::from("12");
+    /// assert_eq!(value.clone().with_type_of(&ConfigValue::Int(0)), ConfigValue::Int(12));
+    /// let drive = ConfigValue::Text("1571".to_string());
+    /// assert_eq!(value.clone().with_type_of(&drive), value);
+    /// ```
+    pub fn with_type_of(self, reference: &ConfigValue) -> Self {
+        match (self, reference) {
+            (Self::Text(s), Self::Int(_)) => s
+                .trim()
+                .parse::<i64>()
+                .map(Self::Int)
+                .unwrap_or(Self::Text(s)),
+            (value, _) => value,
+        }
+    }
+}
+
+impl From<&str> for ConfigValue {
+    /// Text value; see [`ConfigValue::with_type_of`] for numeric items
+    ///
+    /// # Examples
+    /// ```
+    /// use ultimate64::config::ConfigValue;
+    /// assert_eq!(ConfigValue::from("1541"), ConfigValue::Text("1541".to_string()));
+    /// ```
+    fn from(s: &str) -> Self {
+        Self::Text(s.to_string())
+    }
+}
+
+impl Display for ConfigValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Int(i) => write!(f, "{i}"),
+            Self::Text(s) => write!(f, "{s}"),
+        }
+    }
+}
+
+/// Detailed description of a configuration item, including allowed values
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConfigItem {
+    /// Current value
+    pub current: ConfigValue,
+    /// Minimum value (integer items only)
+    pub min: Option<i64>,
+    /// Maximum value (integer items only)
+    pub max: Option<i64>,
+    /// Display format (integer items only)
+    pub format: Option<String>,
+    /// Factory default value
+    pub default: Option<ConfigValue>,
+    /// Allowed values (string items only)
+    pub values: Option<Vec<String>>,
+    /// Suggested values for items that also accept free input
+    pub presets: Option<Vec<String>>,
+}
+
+impl ConfigItem {
+    /// Human readable description of the allowed values
+    pub fn allowed(&self) -> String {
+        if let Some(values) = &self.values {
+            return values.join(", ");
+        }
+        if let Some(presets) = &self.presets {
+            return presets.join(", ");
+        }
+        match (self.min, self.max) {
+            (Some(min), Some(max)) => format!("{min}..={max}"),
+            _ => String::new(),
+        }
+    }
+}
+
+/// Items in a configuration category, ordered by name
+pub type ConfigCategory = BTreeMap<String, ConfigValue>;
+
+/// Configuration categories, ordered by name
+///
+/// This is also the JSON layout used when setting several items at once.
+pub type Configuration = BTreeMap<String, ConfigCategory>;
+
+/// Items in `target` whose value differs from, or is missing in, `current`
+///
+/// # Examples
+/// ```
+/// use ultimate64::config::{changed_items, ConfigValue, Configuration};
+/// let current = Configuration::from([(
+///     "Video".to_string(),
+///     [("Mode".to_string(), ConfigValue::from("PAL")), ("Lines".to_string(), ConfigValue::from("312"))].into(),
+/// )]);
+/// let mut target = current.clone();
+/// target.get_mut("Video").unwrap().insert("Mode".to_string(), ConfigValue::from("NTSC"));
+/// let changes = changed_items(&current, &target);
+/// assert_eq!(changes["Video"].len(), 1);
+/// assert_eq!(changes["Video"]["Mode"], ConfigValue::from("NTSC"));
+/// assert!(changed_items(&current, &current).is_empty());
+/// ```
+pub fn changed_items(current: &Configuration, target: &Configuration) -> Configuration {
+    target
+        .iter()
+        .map(|(category, items)| {
+            let changed: ConfigCategory = items
+                .iter()
+                .filter(|(item, value)| {
+                    current.get(category).and_then(|c| c.get(*item)) != Some(*value)
+                })
+                .map(|(item, value)| (item.clone(), value.clone()))
+                .collect();
+            (category.clone(), changed)
+        })
+        .filter(|(_, items)| !items.is_empty())
+        .collect()
+}
+
+/// Configuration item that differs between two configurations
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ConfigDifference {
+    /// Category name
+    pub category: String,
+    /// Item name
+    pub item: String,
+    /// Value in the first configuration, if present
+    pub left: Option<ConfigValue>,
+    /// Value in the second configuration, if present
+    pub right: Option<ConfigValue>,
+}
+
+/// List items that differ between `left` and `right`, ordered by category and item
+///
+/// Items only present in one of the configurations are included as well.
+///
+/// # Examples
+/// ```
+/// use ultimate64::config::{diff, ConfigValue, Configuration};
+/// let left = Configuration::from([("Video".to_string(), [("Mode".to_string(), ConfigValue::from("PAL"))].into())]);
+/// let right = Configuration::from([("Video".to_string(), [("Mode".to_string(), ConfigValue::from("NTSC"))].into())]);
+/// let differences = diff(&left, &right);
+/// assert_eq!(differences.len(), 1);
+/// assert_eq!(differences[0].left, Some(ConfigValue::from("PAL")));
+/// assert!(diff(&left, &left).is_empty());
+/// ```
+pub fn diff(left: &Configuration, right: &Configuration) -> Vec<ConfigDifference> {
+    let lookup = |config: &Configuration, category: &str, item: &str| {
+        config.get(category).and_then(|c| c.get(item)).cloned()
+    };
+    let keys: std::collections::BTreeSet<(&String, &String)> = left
+        .iter()
+        .chain(right.iter())
+        .flat_map(|(category, items)| items.keys().map(move |item| (category, item)))
+        .collect();
+    keys.into_iter()
+        .map(|(category, item)| ConfigDifference {
+            category: category.clone(),
+            item: item.clone(),
+            left: lookup(left, category, item),
+            right: lookup(right, category, item),
+        })
+        .filter(|d| d.left != d.right)
+        .collect()
+}
+
+/// File formats for configuration profiles
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, ValueEnum, PartialEq, Eq, Default)]
+pub enum ProfileFormat {
+    /// TOML with one table per category
+    #[default]
+    #[clap(name = "toml")]
+    Toml,
+    /// JSON, same layout as the REST API
+    #[clap(name = "json")]
+    Json,
+}
+
+impl ProfileFormat {
+    /// Deduce format from file extension; anything but `.json` is TOML
+    pub fn from_file_name<T: AsRef<Path>>(path: T) -> Self {
+        match auxiliary::get_extension(path).as_deref() {
+            Some("json") => Self::Json,
+            _ => Self::Toml,
+        }
+    }
+
+    /// Serialize configuration to a string in this format
+    pub fn serialize(&self, config: &Configuration) -> Result<String> {
+        Ok(match self {
+            Self::Toml => toml::to_string(config)?,
+            Self::Json => serde_json::to_string_pretty(config)?,
+        })
+    }
+
+    /// Parse configuration from a string in this format
+    pub fn parse(&self, s: &str) -> Result<Configuration> {
+        Ok(match self {
+            Self::Toml => toml::from_str(s)?,
+            Self::Json => serde_json::from_str(s)?,
+        })
+    }
+}
+
+/// Read configuration profile from TOML or JSON file
+pub fn read_profile<P: AsRef<Path>>(path: P) -> Result<Configuration> {
+    let content = std::fs::read_to_string(&path)?;
+    ProfileFormat::from_file_name(&path).parse(&content)
+}
+
+/// Raw list of categories as returned by the Ultimate
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub(crate) struct CategoryList {
+    /// Category names
+    pub categories: Vec<String>,
+}
+
+/// Raw configuration response, keyed by category name
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct ConfigResponse<T> {
+    /// Categories with their content
+    #[serde(flatten)]
+    pub categories: BTreeMap<String, BTreeMap<String, T>>,
+    /// Errors reported by the device
+    #[serde(default)]
+    pub errors: Vec<String>,
+}
@@ -0,0 +1,574 @@
+//! # MOS 6502 CPU core
+//!
+//! Interpreter for the documented 6510 instruction set including decimal mode.
+//! Cycle counts are approximate as page crossing penalties are not included.
+
+/// Memory as seen by the CPU
+pub trait Bus {
+    /// Read byte from `address`
+    fn read(&mut self, address: u16) -> u8;
+    /// Write byte to `address`
+    fn write(&mut self, address: u16, value: u8);
+
+    /// Read little endian word from `address`
+    fn read_word(&mut self, address: u16) -> u16 {
+        u16::from_le_bytes([self.read(address), self.read(address.wrapping_add(1))])
+    }
+}
+
+impl Bus for [u8] {
+    fn read(&mut self, address: u16) -> u8 {
+        self[address as usize]
+    }
+    fn write(&mut self, address: u16, value: u8) {
+        self[address as usize] = value;
+    }
+}
+
+/// Status register flags
+pub mod flags {
+    /// Carry
+    pub const C: u8 = 0x01;
+    /// Zero
+    pub const Z: u8 = 0x02;
+    /// Interrupt disable
+    pub const I: u8 = 0x04;
+    /// Decimal mode
+    pub const D: u8 = 0x08;
+    /// Break
+    pub const B: u8 = 0x10;
+    /// Unused, always set when pushed
+    pub const U: u8 = 0x20;
+    /// Overflow
+    pub const V: u8 = 0x40;
+    /// Negative
+    pub const N: u8 = 0x80;
+}
+
+use flags::*;
+
+/// Start of the hardware stack
+const STACK: u16 = 0x0100;
+
+/// Instruction mnemonics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Adc,
+    And,
+    Asl,
+    Bcc,
+    Bcs,
+    Beq,
+    Bit,
+    Bmi,
+    Bne,
+    Bpl,
+    Brk,
+    Bvc,
+    Bvs,
+    Clc,
+    Cld,
+    Cli,
+    Clv,
+    Cmp,
+    Cpx,
+    Cpy,
+    Dec,
+    Dex,
+    Dey,
+    Eor,
+    Inc,
+    Inx,
+    Iny,
+    Jmp,
+    Jsr,
+    Lda,
+    Ldx,
+    Ldy,
+    Lsr,
+    Nop,
+    Ora,
+    Pha,
+    Php,
+    Pla,
+    Plp,
+    Rol,
+    Ror,
+    Rti,
+    Rts,
+    Sbc,
+    Sec,
+    Sed,
+    Sei,
+    Sta,
+    Stx,
+    Sty,
+    Tax,
+    Tay,
+    Tsx,
+    Txa,
+    Txs,
+    Tya,
+}
+
+/// Addressing modes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndirectX,
+    IndirectY,
+    Relative,
+}
+
+/// Mnemonic and addressing mode of an opcode; `None` for undocumented opcodes
+#[rustfmt::skip]
+fn decode(opcode: u8) -> Option<(Op, Mode)> {
+    use Mode::*;
+    use Op::*;
+    let decoded = match opcode {
+        0x69 => (Adc, Immediate), 0x65 => (Adc, ZeroPage), 0x75 => (Adc, ZeroPageX), 0x6d => (Adc, Absolute),
+        0x7d => (Adc, AbsoluteX), 0x79 => (Adc, AbsoluteY), 0x61 => (Adc, IndirectX), 0x71 => (Adc, IndirectY),
+        0x29 => (And, Immediate), 0x25 => (And, ZeroPage), 0x35 => (And, ZeroPageX), 0x2d => (And, Absolute),
+        0x3d => (And, AbsoluteX), 0x39 => (And, AbsoluteY), 0x21 => (And, IndirectX), 0x31 => (And, IndirectY),
+        0x0a => (Asl, Accumulator), 0x06 => (Asl, ZeroPage), 0x16 => (Asl, ZeroPageX), 0x0e => (Asl, Absolute),
+        0x1e => (Asl, AbsoluteX),
+        0x90 => (Bcc, Relative), 0xb0 => (Bcs, Relative), 0xf0 => (Beq, Relative), 0x30 => (Bmi, Relative),
+        0xd0 => (Bne, Relative), 0x10 => (Bpl, Relative), 0x50 => (Bvc, Relative), 0x70 => (Bvs, Relative),
+        0x24 => (Bit, ZeroPage), 0x2c => (Bit, Absolute),
+        0x00 => (Brk, Implied),
+        0x18 => (Clc, Implied), 0xd8 => (Cld, Implied), 0x58 => (Cli, Implied), 0xb8 => (Clv, Implied),
+        0xc9 => (Cmp, Immediate), 0xc5 => (Cmp, ZeroPage), 0xd5 => (Cmp, ZeroPageX), 0xcd => (Cmp, Absolute),
+        0xdd => (Cmp, AbsoluteX), 0xd9 => (Cmp, AbsoluteY), 0xc1 => (Cmp, IndirectX), 0xd1 => (Cmp, IndirectY),
+        0xe0 => (Cpx, Immediate), 0xe4 => (Cpx, ZeroPage), 0xec => (Cpx, Absolute),
+        0xc0 => (Cpy, Immediate), 0xc4 => (Cpy, ZeroPage), 0xcc => (Cpy, Absolute),
+        0xc6 => (Dec, ZeroPage), 0xd6 => (Dec, ZeroPageX), 0xce => (Dec, Absolute), 0xde => (Dec, AbsoluteX),
+        0xca => (Dex, Implied), 0x88 => (Dey, Implied),
+        0x49 => (Eor, Immediate), 0x45 => (Eor, ZeroPage), 0x55 => (Eor, ZeroPageX), 0x4d => (Eor, Absolute),
+        0x5d => (Eor, AbsoluteX), 0x59 => (Eor, AbsoluteY), 0x41 => (Eor, IndirectX), 0x51 => (Eor, IndirectY),
+        0xe6 => (Inc, ZeroPage), 0xf6 => (Inc, ZeroPageX), 0xee => (Inc, Absolute), 0xfe => (Inc, AbsoluteX),
+        0xe8 => (Inx, Implied), 0xc8 => (Iny, Implied),
+        0x4c => (Jmp, Absolute), 0x6c => (Jmp, Indirect), 0x20 => (Jsr, Absolute),
+        0xa9 => (Lda, Immediate), 0xa5 => (Lda, ZeroPage), 0xb5 => (Lda, ZeroPageX), 0xad => (Lda, Absolute),
+        0xbd => (Lda, AbsoluteX), 0xb9 => (Lda, AbsoluteY), 0xa1 => (Lda, IndirectX), 0xb1 => (Lda, IndirectY),
+        0xa2 => (Ldx, Immediate), 0xa6 => (Ldx, ZeroPage), 0xb6 => (Ldx, ZeroPageY), 0xae => (Ldx, Absolute),
+        0xbe => (Ldx, AbsoluteY),
+        0xa0 => (Ldy, Immediate), 0xa4 => (Ldy, ZeroPage), 0xb4 => (Ldy, ZeroPageX), 0xac => (Ldy, Absolute),
+        0xbc => (Ldy, AbsoluteX),
+        0x4a => (Lsr, Accumulator), 0x46 => (Lsr, ZeroPage), 0x56 => (Lsr, ZeroPageX), 0x4e => (Lsr, Absolute),
+        0x5e => (Lsr, AbsoluteX),
+        0xea => (Nop, Implied),
+        0x09 => (Ora, Immediate), 0x05 => (Ora, ZeroPage), 0x15 => (Ora, ZeroPageX), 0x0d => (Ora, Absolute),
+        0x1d => (Ora, AbsoluteX), 0x19 => (Ora, AbsoluteY), 0x01 => (Ora, IndirectX), 0x11 => (Ora, IndirectY),
+        0x48 => (Pha, Implied), 0x08 => (Php, Implied), 0x68 => (Pla, Implied), 0x28 => (Plp, Implied),
+        0x2a => (Rol, Accumulator), 0x26 => (Rol, ZeroPage), 0x36 => (Rol, ZeroPageX), 0x2e => (Rol, Absolute),
+        0x3e => (Rol, AbsoluteX),
+        0x6a => (Ror, Accumulator), 0x66 => (Ror, ZeroPage), 0x76 => (Ror, ZeroPageX), 0x6e => (Ror, Absolute),
+        0x7e => (Ror, AbsoluteX),
+        0x40 => (Rti, Implied), 0x60 => (Rts, Implied),
+        0xe9 => (Sbc, Immediate), 0xe5 => (Sbc, ZeroPage), 0xf5 => (Sbc, ZeroPageX), 0xed => (Sbc, Absolute),
+        0xfd => (Sbc, AbsoluteX), 0xf9 => (Sbc, AbsoluteY), 0xe1 => (Sbc, IndirectX), 0xf1 => (Sbc, IndirectY),
+        0x38 => (Sec, Implied), 0xf8 => (Sed, Implied), 0x78 => (Sei, Implied),
+        0x85 => (Sta, ZeroPage), 0x95 => (Sta, ZeroPageX), 0x8d => (Sta, Absolute), 0x9d => (Sta, AbsoluteX),
+        0x99 => (Sta, AbsoluteY), 0x81 => (Sta, IndirectX), 0x91 => (Sta, IndirectY),
+        0x86 => (Stx, ZeroPage), 0x96 => (Stx, ZeroPageY), 0x8e => (Stx, Absolute),
+        0x84 => (Sty, ZeroPage), 0x94 => (Sty, ZeroPageX), 0x8c => (Sty, Absolute),
+        0xaa => (Tax, Implied), 0xa8 => (Tay, Implied), 0xba => (Tsx, Implied), 0x8a => (Txa, Implied),
+        0x9a => (Txs, Implied), 0x98 => (Tya, Implied),
+        _ => return None,
+    };
+    Some(decoded)
+}
+
+/// Base number of cycles for an instruction
+fn cycles(op: Op, mode: Mode) -> u8 {
+    use Mode::*;
+    use Op::*;
+    let read_modify_write = matches!(op, Asl | Lsr | Rol | Ror | Inc | Dec);
+    let store = matches!(op, Sta | Stx | Sty);
+    match (op, mode) {
+        (Brk, _) => 7,
+        (Jsr | Rts | Rti, _) => 6,
+        (Jmp, Absolute) => 3,
+        (Jmp, _) => 5,
+        (Pha | Php, _) => 3,
+        (Pla | Plp, _) => 4,
+        (_, Implied | Accumulator | Immediate | Relative) => 2,
+        (_, ZeroPage) if read_modify_write => 5,
+        (_, ZeroPageX) | (_, Absolute) if read_modify_write => 6,
+        (_, AbsoluteX) if read_modify_write => 7,
+        (_, ZeroPage) => 3,
+        (_, ZeroPageX | ZeroPageY | Absolute) => 4,
+        (_, AbsoluteX | AbsoluteY) if store => 5,
+        (_, AbsoluteX | AbsoluteY) => 4,
+        (_, IndirectX) => 6,
+        (_, IndirectY) if store => 6,
+        (_, IndirectY) => 5,
+        (_, Indirect) => 5,
+    }
+}
+
+/// Registers and cycle counter of a 6502 CPU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cpu {
+    /// Accumulator
+    pub a: u8,
+    /// X index register
+    pub x: u8,
+    /// Y index register
+    pub y: u8,
+    /// Stack pointer
+    pub sp: u8,
+    /// Program counter
+    pub pc: u16,
+    /// Processor status
+    pub status: u8,
+    /// Elapsed cycles
+    pub cycles: u64,
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self {
+            a: 0,
+            x: 0,
+            y: 0,
+            sp: 0xfd,
+            pc: 0,
+            status: U | I,
+            cycles: 0,
+        }
+    }
+}
+
+impl Cpu {
+    /// Execute a single instruction and return the number of cycles spent
+    ///
+    /// Returns `None`, without touching the registers, if the opcode at the
+    /// program counter is undocumented.
+    pub fn step<B: Bus + ?Sized>(&mut self, bus: &mut B) -> Option<u8> {
+        let (op, mode) = decode(bus.read(self.pc))?;
+        self.pc = self.pc.wrapping_add(1);
+        let mut cycles = cycles(op, mode);
+        let address = self.operand_address(bus, mode);
+        use Op::*;
+        match op {
+            Adc => {
+                let value = bus.read(address);
+                self.add(value);
+            }
+            Sbc => {
+                let value = bus.read(address);
+                self.subtract(value);
+            }
+            And => self.a = self.set_nz(self.a & bus.read(address)),
+            Ora => self.a = self.set_nz(self.a | bus.read(address)),
+            Eor => self.a = self.set_nz(self.a ^ bus.read(address)),
+            Bit => {
+                let value = bus.read(address);
+                self.set_flag(Z, self.a & value == 0);
+                self.status = (self.status & !(N | V)) | (value & (N | V));
+            }
+            Cmp => self.compare(self.a, bus.read(address)),
+            Cpx => self.compare(self.x, bus.read(address)),
+            Cpy => self.compare(self.y, bus.read(address)),
+            Lda => self.a = self.set_nz(bus.read(address)),
+            Ldx => self.x = self.set_nz(bus.read(address)),
+            Ldy => self.y = self.set_nz(bus.read(address)),
+            Sta => bus.write(address, self.a),
+            Stx => bus.write(address, self.x),
+            Sty => bus.write(address, self.y),
+            Asl | Lsr | Rol | Ror | Inc | Dec => {
+                let value = match mode {
+                    Mode::Accumulator => self.a,
+                    _ => bus.read(address),
+                };
+                let result = self.read_modify_write(op, value);
+                match mode {
+                    Mode::Accumulator => self.a = result,
+                    _ => bus.write(address, result),
+                }
+            }
+            Inx => self.x = self.set_nz(self.x.wrapping_add(1)),
+            Iny => self.y = self.set_nz(self.y.wrapping_add(1)),
+            Dex => self.x = self.set_nz(self.x.wrapping_sub(1)),
+            Dey => self.y = self.set_nz(self.y.wrapping_sub(1)),
+            Tax => self.x = self.set_nz(self.a),
+            Tay => self.y = self.set_nz(self.a),
+            Txa => self.a = self.set_nz(self.x),
+            Tya => self.a = self.set_nz(self.y),
+            Tsx => self.x = self.set_nz(self.sp),
+            Txs => self.sp = self.x,
+            Pha => self.push(bus, self.a),
+            Php => self.push(bus, self.status | B | U),
+            Pla => {
+                let value = self.pull(bus);
+                self.a = self.set_nz(value);
+            }
+            Plp => self.status = self.pull(bus) | U,
+            Clc => self.set_flag(C, false),
+            Cld => self.set_flag(D, false),
+            Cli => self.set_flag(I, false),
+            Clv => self.set_flag(V, false),
+            Sec => self.set_flag(C, true),
+            Sed => self.set_flag(D, true),
+            Sei => self.set_flag(I, true),
+            Bcc | Bcs | Beq | Bne | Bmi | Bpl | Bvc | Bvs => {
+                let (flag, set) = match op {
+                    Bcc => (C, false),
+                    Bcs => (C, true),
+                    Bne => (Z, false),
+                    Beq => (Z, true),
+                    Bpl => (N, false),
+                    Bmi => (N, true),
+                    Bvc => (V, false),
+                    _ => (V, true),
+                };
+                if (self.status & flag != 0) == set {
+                    self.pc = address;
+                    cycles += 1;
+                }
+            }
+            Jmp => self.pc = address,
+            Jsr => {
+                self.push_word(bus, self.pc.wrapping_sub(1));
+                self.pc = address;
+            }
+            Rts => self.pc = self.pull_word(bus).wrapping_add(1),
+            Rti => {
+                self.status = self.pull(bus) | U;
+                self.pc = self.pull_word(bus);
+            }
+            Brk => {
+                self.push_word(bus, self.pc.wrapping_add(1));
+                self.push(bus, self.status | B | U);
+                self.set_flag(I, true);
+                self.pc = bus.read_word(0xfffe);
+            }
+            Nop => {}
+        }
+        self.cycles += cycles as u64;
+        Some(cycles)
+    }
+
+    /// Push return address and jump to `address` as if called with JSR
+    pub fn call<B: Bus + ?Sized>(&mut self, bus: &mut B, address: u16, return_address: u16) {
+        self.push_word(bus, return_address.wrapping_sub(1));
+        self.pc = address;
+    }
+
+    /// Effective address of the operand; advances the program counter past it
+    fn operand_address<B: Bus + ?Sized>(&mut self, bus: &mut B, mode: Mode) -> u16 {
+        let pc = self.pc;
+        let zero_page = |bus: &mut B, address: u8| {
+            u16::from_le_bytes([
+                bus.read(address as u16),
+                bus.read(address.wrapping_add(1) as u16),
+            ])
+        };
+        let (address, length) = match mode {
+            Mode::Implied | Mode::Accumulator => (0, 0),
+            Mode::Immediate => (pc, 1),
+            Mode::ZeroPage => (bus.read(pc) as u16, 1),
+            Mode::ZeroPageX => (bus.read(pc).wrapping_add(self.x) as u16, 1),
+            Mode::ZeroPageY => (bus.read(pc).wrapping_add(self.y) as u16, 1),
+            Mode::Absolute => (bus.read_word(pc), 2),
+            Mode::AbsoluteX => (bus.read_word(pc).wrapping_add(self.x as u16), 2),
+            Mode::AbsoluteY => (bus.read_word(pc).wrapping_add(self.y as u16), 2),
+            Mode::Indirect => {
+                // the 6502 does not carry into the high byte when fetching the vector
+                let vector = bus.read_word(pc);
+                let high = (vector & 0xff00) | (vector.wrapping_add(1) & 0x00ff);
+                (u16::from_le_bytes([bus.read(vector), bus.read(high)]), 2)
+            }
+            Mode::IndirectX => {
+                let pointer = bus.read(pc).wrapping_add(self.x);
+                (zero_page(bus, pointer), 1)
+            }
+            Mode::IndirectY => {
+                let base = bus.read(pc);
+                (zero_page(bus, base).wrapping_add(self.y as u16), 1)
+            }
+            Mode::Relative => {
+                let offset = bus.read(pc) as i8;
+                (pc.wrapping_add(1).wrapping_add_signed(offset as i16), 1)
+            }
+        };
+        self.pc = pc.wrapping_add(length);
+        address
+    }
+
+    fn read_modify_write(&mut self, op: Op, value: u8) -> u8 {
+        let carry = self.status & C;
+        let (result, carry_out) = match op {
+            Op::Asl => (value << 1, value & 0x80 != 0),
+            Op::Lsr => (value >> 1, value & 0x01 != 0),
+            Op::Rol => ((value << 1) | carry, value & 0x80 != 0),
+            Op::Ror => ((value >> 1) | (carry << 7), value & 0x01 != 0),
+            Op::Inc => return self.set_nz(value.wrapping_add(1)),
+            _ => return self.set_nz(value.wrapping_sub(1)),
+        };
+        self.set_flag(C, carry_out);
+        self.set_nz(result)
+    }
+
+    fn add(&mut self, value: u8) {
+        let carry = (self.status & C) as u16;
+        let binary = self.a as u16 + value as u16 + carry;
+        self.set_flag(V, (!(self.a ^ value) & (self.a ^ binary as u8)) & 0x80 != 0);
+        if self.status & D == 0 {
+            self.set_flag(C, binary > 0xff);
+            self.a = self.set_nz(binary as u8);
+            return;
+        }
+        let mut low = (self.a & 0x0f) as u16 + (value & 0x0f) as u16 + carry;
+        if low > 9 {
+            low += 6;
+        }
+        let mut result = (self.a & 0xf0) as u16 + (value & 0xf0) as u16 + low;
+        if result > 0x9f {
+            result += 0x60;
+        }
+        self.set_flag(C, result > 0xff);
+        self.set_flag(Z, binary as u8 == 0);
+        self.set_flag(N, result & 0x80 != 0);
+        self.a = result as u8;
+    }
+
+    fn subtract(&mut self, value: u8) {
+        if self.status & D == 0 {
+            return self.add(!value);
+        }
+        let borrow = 1 - (self.status & C) as i16;
+        let binary = self.a as i16 - value as i16 - borrow;
+        self.set_flag(V, ((self.a ^ value) & (self.a ^ binary as u8)) & 0x80 != 0);
+        let mut low = (self.a & 0x0f) as i16 - (value & 0x0f) as i16 - borrow;
+        let mut high = (self.a >> 4) as i16 - (value >> 4) as i16;
+        if low < 0 {
+            low -= 6;
+            high -= 1;
+        }
+        if high < 0 {
+            high -= 6;
+        }
+        self.set_flag(C, binary >= 0);
+        self.set_nz(binary as u8);
+        self.a = ((high << 4) as u8) | (low as u8 & 0x0f);
+    }
+
+    fn compare(&mut self, register: u8, value: u8) {
+        self.set_flag(C, register >= value);
+        self.set_nz(register.wrapping_sub(value));
+    }
+
+    fn set_flag(&mut self, flag: u8, on: bool) {
+        if on {
+            self.status |= flag;
+        } else {
+            self.status &= !flag;
+        }
+    }
+
+    /// Update zero and negative flags from `value` and return it
+    fn set_nz(&mut self, value: u8) -> u8 {
+        self.set_flag(Z, value == 0);
+        self.set_flag(N, value & 0x80 != 0);
+        value
+    }
+
+    fn push<B: Bus + ?Sized>(&mut self, bus: &mut B, value: u8) {
+        bus.write(STACK | self.sp as u16, value);
+        self.sp = self.sp.wrapping_sub(1);
+    }
+
+    fn pull<B: Bus + ?Sized>(&mut self, bus: &mut B) -> u8 {
+        self.sp = self.sp.wrapping_add(1);
+        bus.read(STACK | self.sp as u16)
+    }
+
+    fn push_word<B: Bus + ?Sized>(&mut self, bus: &mut B, value: u16) {
+        let [low, high] = value.to_le_bytes();
+        self.push(bus, high);
+        self.push(bus, low);
+    }
+
+    fn pull_word<B: Bus + ?Sized>(&mut self, bus: &mut B) -> u16 {
+        let low = self.pull(bus);
+        let high = self.pull(bus);
+        u16::from_le_bytes([low, high])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run code placed at $1000 until BRK
+    fn run(code: &[u8]) -> (Cpu, Vec<u8>) {
+        let mut ram = vec![0; 0x10000];
+        ram[0x1000..0x1000 + code.len()].copy_from_slice(code);
+        let mut cpu = Cpu {
+            pc: 0x1000,
+            ..Default::default()
+        };
+        while ram[cpu.pc as usize] != 0x00 {
+            cpu.step(ram.as_mut_slice()).unwrap();
+        }
+        (cpu, ram)
+    }
+
+    #[test]
+    fn test_loop_and_store() {
+        // ldx #$05; loop: txa; sta $2000,x; dex; bne loop; brk
+        let (cpu, ram) = run(&[0xa2, 0x05, 0x8a, 0x9d, 0x00, 0x20, 0xca, 0xd0, 0xf9]);
+        assert_eq!(ram[0x2001..0x2006], [1, 2, 3, 4, 5]);
+        assert_eq!(cpu.x, 0);
+        assert_ne!(cpu.status & Z, 0);
+    }
+
+    #[test]
+    fn test_subroutine() {
+        // jsr $1010; sta $2000; brk ... $1010: lda #$42; rts
+        let mut code = vec![0xea; 0x12];
+        code[..7].copy_from_slice(&[0x20, 0x10, 0x10, 0x8d, 0x00, 0x20, 0x00]);
+        code[0x10..].copy_from_slice(&[0xa9, 0x42]);
+        code.push(0x60);
+        let (cpu, ram) = run(&code);
+        assert_eq!(ram[0x2000], 0x42);
+        assert_eq!(cpu.sp, 0xfd);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        // clc; lda #$7f; adc #$01
+        let (cpu, _) = run(&[0x18, 0xa9, 0x7f, 0x69, 0x01]);
+        assert_eq!(cpu.a, 0x80);
+        assert_eq!(cpu.status & (V | N | C), V | N);
+        // sec; lda #$00; sbc #$01
+        let (cpu, _) = run(&[0x38, 0xa9, 0x00, 0xe9, 0x01]);
+        assert_eq!(cpu.a, 0xff);
+        assert_eq!(cpu.status & C, 0);
+        // sed; clc; lda #$19; adc #$28
+        let (cpu, _) = run(&[0xf8, 0x18, 0xa9, 0x19, 0x69, 0x28]);
+        assert_eq!(cpu.a, 0x47);
+        // sed; sec; lda #$42; sbc #$13
+        let (cpu, _) = run(&[0xf8, 0x38, 0xa9, 0x42, 0xe9, 0x13]);
+        assert_eq!(cpu.a, 0x29);
+    }
+
+    #[test]
+    fn test_undocumented_opcode() {
+        let mut ram = vec![0x02; 0x10000];
+        let mut cpu = Cpu::default();
+        assert_eq!(cpu.step(ram.as_mut_slice()), None);
+        assert_eq!(cpu, Cpu::default());
+    }
+}
@@ -0,0 +1,224 @@
+//! # Discovery of devices on the local network
+//!
+//! Probes `/v1/info` on every address of one or more IPv4 subnets in
+//! parallel, by default the subnets of the local network interfaces. Devices
+//! can then be found by their unique ID, which survives DHCP address changes.
+//!
+//! # Examples
+//! ~~~ rust, ignore
+//! use ultimate64::discovery::{self, Scan};
+//! let scan = Scan::new(discovery::local_subnets()?);
+//! for (ip, info) in scan.run()? {
+//!     println!("{ip}: {info}");
+//! }
+//! ~~~
+
+use crate::{DeviceInfo, Error, Rest, RestBuilder, Result};
+use core::fmt::Display;
+use if_addrs::{IfAddr, IfOperStatus};
+use log::{debug, warn};
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    str::FromStr,
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+use url::Host;
+
+/// Maximum number of addresses probed at the same time
+const MAX_PARALLEL_PROBES: usize = 64;
+
+/// Smallest prefix length accepted, limiting a scan to 65536 addresses
+const MIN_PREFIX: u8 = 16;
+
+/// IPv4 network in CIDR notation, e.g. `192.168.1.0/24`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subnet {
+    /// Network address
+    network: Ipv4Addr,
+    /// Prefix length
+    prefix: u8,
+}
+
+impl Subnet {
+    /// New subnet containing `address`; host bits are cleared
+    pub fn new(address: Ipv4Addr, prefix: u8) -> Result<Self> {
+        if !(MIN_PREFIX..=32).contains(&prefix) {
+            return Err(Error::invalid(format!(
+                "subnet prefix must be between {MIN_PREFIX} and 32, got {prefix}"
+            )));
+        }
+        let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+        Ok(Self {
+            network: Ipv4Addr::from(u32::from(address) & mask),
+            prefix,
+        })
+    }
+
+    /// Host addresses, excluding network and broadcast address for prefixes below /31
+    ///
+    /// # Examples
+    /// ```
+    /// use ultimate64::discovery::Subnet;
+    /// let subnet: Subnet = "10.0.0.77/30".parse().unwrap();
+    /// let hosts: Vec<_> = subnet.hosts().map(|ip| ip.to_string()).collect();
+    /// assert_eq!(hosts, ["10.0.0.77", "10.0.0.78"]);
+    /// assert_eq!("10.0.0.1/32".parse::<Subnet>().unwrap().hosts().count(), 1);
+    /// ```
+    pub fn hosts(&self) -> impl Iterator<Item = Ipv4Addr> {
+        let first = u32::from(self.network);
+        let size = 1u64 << (32 - self.prefix);
+        let (skip, take) = if size <= 2 { (0, size) } else { (1, size - 2) };
+        (first as u64 + skip..first as u64 + skip + take).map(|ip| Ipv4Addr::from(ip as u32))
+    }
+}
+
+impl FromStr for Subnet {
+    type Err = Error;
+
+    /// Parse `address/prefix`; a plain address is taken as a /24 network
+    fn from_str(s: &str) -> Result<Self> {
+        let (address, prefix) = s.split_once('/').unwrap_or((s, "24"));
+        let address = address
+            .parse()
+            .map_err(|_| Error::invalid(format!("invalid IPv4 subnet: {s}")))?;
+        let prefix = prefix
+            .parse()
+            .map_err(|_| Error::invalid(format!("invalid subnet prefix: {prefix}")))?;
+        Self::new(address, prefix)
+    }
+}
+
+impl Display for Subnet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+/// Subnets of the IPv4 network interfaces, using each interface's netmask
+///
+/// Interfaces that are down, loopback or link-local are skipped. Networks
+/// larger than /16 are narrowed to the /16 around the interface address.
+pub fn local_subnets() -> Result<Vec<Subnet>> {
+    let mut subnets = Vec::new();
+    for interface in if_addrs::get_if_addrs()? {
+        let IfAddr::V4(address) = &interface.addr else {
+            continue;
+        };
+        if interface.is_loopback()
+            || interface.is_link_local()
+            || interface.oper_status == IfOperStatus::Down
+        {
+            continue;
+        }
+        let prefix = address.prefixlen.max(MIN_PREFIX);
+        if prefix != address.prefixlen {
+            warn!(
+                "Scanning only {}/{prefix} of {}/{} on {}",
+                address.ip, address.ip, address.prefixlen, interface.name
+            );
+        }
+        subnets.push(Subnet::new(address.ip, prefix)?);
+    }
+    subnets.sort_by_key(|subnet| (u32::from(subnet.network), subnet.prefix));
+    subnets.dedup();
+    if subnets.is_empty() {
+        return Err(Error::invalid(
+            "no IPv4 network interface found; give a subnet to scan",
+        ));
+    }
+    Ok(subnets)
+}
+
+/// Parallel scan of subnets for Ultimate devices
+#[derive(Debug, Clone)]
+pub struct Scan {
+    /// Networks to scan
+    pub subnets: Vec<Subnet>,
+    /// TCP port of the REST API
+    pub port: u16,
+    /// Network password, if set on the devices
+    pub password: Option<String>,
+    /// Time allowed per address for connecting and answering
+    pub timeout: Duration,
+}
+
+impl Scan {
+    /// Scan of `subnets` on port 80 without password and a 500 ms timeout
+    pub fn new(subnets: Vec<Subnet>) -> Self {
+        Self {
+            subnets,
+            port: 80,
+            password: None,
+            timeout: Duration::from_millis(500),
+        }
+    }
+
+    /// Addresses and information of all devices answering, sorted by address
+    pub fn run(&self) -> Result<Vec<(IpAddr, DeviceInfo)>> {
+        let client = RestBuilder::new(&Host::Ipv4(Ipv4Addr::UNSPECIFIED))
+            .connect_timeout(self.timeout)
+            .timeout(self.timeout)
+            .http_client()?;
+        let hosts = Mutex::new(self.subnets.iter().flat_map(Subnet::hosts));
+        let found = Mutex::new(Vec::new());
+        let next_host = || hosts.lock().unwrap_or_else(|e| e.into_inner()).next();
+        let workers = self
+            .subnets
+            .iter()
+            .map(|subnet| subnet.hosts().size_hint().0)
+            .sum::<usize>()
+            .min(MAX_PARALLEL_PROBES);
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| {
+                    while let Some(ip) = next_host() {
+                        let target = RestBuilder::new(&Host::Ipv4(ip))
+                            .port(self.port)
+                            .password(self.password.clone())
+                            .target();
+                        match target.and_then(|t| Rest::from_parts(client.clone(), t).info()) {
+                            Ok(info) => {
+                                debug!("Found {} at {ip}", info.product);
+                                found
+                                    .lock()
+                                    .unwrap_or_else(|e| e.into_inner())
+                                    .push((IpAddr::V4(ip), info));
+                            }
+                            Err(Error::Auth) => debug!("Device at {ip} rejected the password"),
+                            Err(_) => {}
+                        }
+                    }
+                });
+            }
+        });
+        let mut found = found.into_inner().unwrap_or_else(|e| e.into_inner());
+        found.sort_by_key(|(ip, _)| *ip);
+        Ok(found)
+    }
+
+    /// Address of the device with the given unique ID (case insensitive)
+    pub fn resolve(&self, unique_id: &str) -> Result<IpAddr> {
+        self.run()?
+            .into_iter()
+            .find(|(_, info)| {
+                info.unique_id
+                    .as_deref()
+                    .is_some_and(|id| id.eq_ignore_ascii_case(unique_id))
+            })
+            .map(|(ip, _)| ip)
+            .ok_or_else(|| {
+                let subnets: Vec<_> = self.subnets.iter().map(Subnet::to_string).collect();
+                Error::invalid(format!(
+                    "no device with unique ID {unique_id} found in {}",
+                    subnets.join(", ")
+                ))
+            })
+    }
+}
+
+/// Devices on `subnets` using default [`Scan`] settings
+pub fn discover(subnets: Vec<Subnet>) -> Result<Vec<(IpAddr, DeviceInfo)>> {
+    Scan::new(subnets).run()
+}
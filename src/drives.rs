@@ -1,7 +1,6 @@
 //! # Disk drive and disk image manipulation
 
-use crate::auxiliary;
-use anyhow::{bail, Result};
+use crate::{auxiliary, Error, Result};
 use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, fmt::Display, path::Path};
@@ -10,12 +9,16 @@ use std::{collections::HashMap, fmt::Display, path::Path};
 #[derive(Serialize, Deserialize, Debug, Clone, ValueEnum, PartialEq, Eq, PartialOrd, Ord)]
 pub enum DriveType {
     #[serde(rename = "1541")]
+    #[clap(name = "1541")]
     CBM1541,
     #[serde(rename = "1571")]
+    #[clap(name = "1571")]
     CBM1571,
     #[serde(rename = "1581")]
+    #[clap(name = "1581")]
     CBM1581,
     #[serde(rename = "DOS emulation")]
+    #[clap(name = "dos")]
     DOS,
 }
 
@@ -31,6 +34,66 @@ impl Display for DriveType {
     }
 }
 
+impl DriveType {
+    /// Allowed ROM image sizes in bytes for this drive type
+    pub const fn rom_sizes(&self) -> &'static [usize] {
+        match self {
+            Self::CBM1541 => &[0x4000, 0x8000],
+            Self::CBM1571 | Self::CBM1581 => &[0x8000],
+            Self::DOS => &[],
+        }
+    }
+
+    /// Check that a ROM image of `len` bytes fits this drive type
+    ///
+    /// # Examples
+    /// ```
+    /// use ultimate64::drives::DriveType;
+    /// assert!(DriveType::CBM1541.check_rom_size(16384).is_ok());
+    /// assert!(DriveType::CBM1541.check_rom_size(32768).is_ok());
+    /// assert!(DriveType::CBM1581.check_rom_size(16384).is_err());
+    /// assert!(DriveType::DOS.check_rom_size(16384).is_err());
+    /// ```
+    pub fn check_rom_size(&self, len: usize) -> Result<()> {
+        let sizes = self.rom_sizes();
+        if !sizes.contains(&len) {
+            let allowed: Vec<String> = sizes.iter().map(|s| format!("{}K", s / 1024)).collect();
+            return Err(Error::invalid(format!(
+                "ROM of {len} bytes does not fit {self} drive (allowed: {})",
+                if allowed.is_empty() {
+                    "none".to_string()
+                } else {
+                    allowed.join(", ")
+                }
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Emulated disk drives that can be controlled individually
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, ValueEnum, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DriveId {
+    /// Drive A
+    #[serde(rename = "a")]
+    #[clap(name = "a")]
+    A,
+    /// Drive B
+    #[serde(rename = "b")]
+    #[clap(name = "b")]
+    B,
+}
+
+impl Display for DriveId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::A => "a",
+            Self::B => "b",
+        };
+        write!(f, "{s}")
+    }
+}
+
 /// Disk image types
 #[derive(Serialize, Deserialize, Debug, Clone, ValueEnum, PartialEq, Eq, PartialOrd, Ord)]
 pub enum DiskImageType {
@@ -44,6 +107,8 @@ pub enum DiskImageType {
     G71,
     #[clap(name = "d81")]
     D81,
+    #[clap(name = "dnp")]
+    DNP,
 }
 
 impl DiskImageType {
@@ -56,7 +121,12 @@ impl DiskImageType {
             "d81" => Self::D81,
             "g64" => Self::G64,
             "g71" => Self::G71,
-            _ => bail!("File extension must be one of: d64, g64, d71, g71, d81"),
+            "dnp" => Self::DNP,
+            _ => {
+                return Err(Error::invalid(
+                    "File extension must be one of: d64, g64, d71, g71, d81, dnp",
+                ))
+            }
         })
     }
 }
@@ -69,6 +139,7 @@ impl Display for DiskImageType {
             Self::D71 => "d71",
             Self::G71 => "g71",
             Self::D81 => "d81",
+            Self::DNP => "dnp",
         };
         write!(f, "{s}")
     }
@@ -0,0 +1,341 @@
+//! # Offline emulated backend
+//!
+//! [`Emulator`] implements [`Ultimate`] on top of a 64K RAM model and the
+//! built-in [6502 core](crate::cpu), so programs can be loaded, run and
+//! inspected without a device attached.
+//!
+//! No ROMs are included: calls into the BASIC and Kernal ROM areas return
+//! immediately, and typed text only understands `RUN` and `SYS <address>`.
+//! Programs can report results through the U64 debug register ($D7FF).
+//!
+//! # Examples
+//! ```
+//! use ultimate64::{emulator::Emulator, Ultimate};
+//! let emulator = Emulator::default();
+//! // lda #$2a; sta $d7ff; rts
+//! emulator.run_prg(&[0x00, 0xc0, 0xa9, 0x2a, 0x8d, 0xff, 0xd7, 0x60]).unwrap();
+//! assert_eq!(emulator.read_debugreg().unwrap(), 0x2a);
+//! ```
+
+use crate::{
+    auxiliary::{basic_sys_address, check_address_overflow, check_data_overflow},
+    cpu::{Bus, Cpu},
+    request, DeviceInfo, Error, Result, Ultimate,
+};
+use core::fmt::Display;
+use log::{debug, info, warn};
+use std::cell::RefCell;
+
+/// Load address of BASIC programs
+const BASIC_START: u16 = 0x0801;
+/// U64 debug register
+const DEBUG_REGISTER: u16 = 0xd7ff;
+/// Return address pushed before calling a program; reached when it returns
+const RETURN_ADDRESS: u16 = 0x0000;
+/// Stack pointer when calling a program
+const INITIAL_SP: u8 = 0xff;
+/// About ten seconds of PAL C64 time
+const DEFAULT_CYCLE_LIMIT: u64 = 10_000_000;
+
+/// Reason why a program stopped executing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Halt {
+    /// Program returned with RTS
+    Returned,
+    /// BRK instruction reached
+    Break,
+    /// Program is spinning in a tight loop, e.g. `jmp *`
+    Idle,
+    /// Undocumented opcode that the CPU core does not implement
+    Illegal(u8),
+    /// Cycle limit reached while still running
+    CycleLimit,
+}
+
+impl Display for Halt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Returned => write!(f, "returned"),
+            Self::Break => write!(f, "break"),
+            Self::Idle => write!(f, "idle loop"),
+            Self::Illegal(opcode) => write!(f, "illegal opcode {opcode:#04x}"),
+            Self::CycleLimit => write!(f, "cycle limit reached"),
+        }
+    }
+}
+
+/// 64K RAM with ROM areas visible according to the processor port at $01
+struct Machine {
+    cpu: Cpu,
+    ram: Vec<u8>,
+}
+
+impl Machine {
+    fn new() -> Self {
+        let mut machine = Self {
+            cpu: Cpu::default(),
+            ram: vec![0; 0x10000],
+        };
+        machine.reset();
+        machine
+    }
+
+    fn reset(&mut self) {
+        self.cpu = Cpu::default();
+        self.ram[0x00] = 0x2f; // processor port data direction
+        self.ram[0x01] = 0x37; // BASIC, Kernal and I/O visible
+        self.ram[0xc6] = 0; // keyboard buffer length
+    }
+
+    /// Whether `address` is in a ROM area currently banked in
+    fn is_rom(&self, address: u16) -> bool {
+        let port = self.ram[0x01];
+        match address {
+            0xa000..=0xbfff => port & 0x03 == 0x03,
+            0xe000..=0xffff => port & 0x02 != 0,
+            _ => false,
+        }
+    }
+
+    /// Call subroutine at `address` and run until it returns or stops
+    fn call(&mut self, address: u16, cycle_limit: u64) -> Halt {
+        self.cpu.sp = INITIAL_SP;
+        let Self { cpu, ram } = self;
+        cpu.call(ram.as_mut_slice(), address, RETURN_ADDRESS);
+        let start = cpu.cycles;
+        loop {
+            if self.cpu.pc == RETURN_ADDRESS && self.cpu.sp == INITIAL_SP {
+                return Halt::Returned;
+            }
+            if self.cpu.cycles - start >= cycle_limit {
+                return Halt::CycleLimit;
+            }
+            if self.is_rom(self.cpu.pc) {
+                // no ROMs available, so behave as if the routine returned at once
+                debug!("Skipping ROM routine at {:#06x}", self.cpu.pc);
+                let Self { cpu, ram } = self;
+                let low = ram.read(0x0100 | cpu.sp.wrapping_add(1) as u16);
+                let high = ram.read(0x0100 | cpu.sp.wrapping_add(2) as u16);
+                cpu.sp = cpu.sp.wrapping_add(2);
+                cpu.pc = u16::from_le_bytes([low, high]).wrapping_add(1);
+                continue;
+            }
+            let pc = self.cpu.pc;
+            let opcode = self.ram[pc as usize];
+            if opcode == 0x00 {
+                return Halt::Break;
+            }
+            let Self { cpu, ram } = self;
+            if cpu.step(ram.as_mut_slice()).is_none() {
+                return Halt::Illegal(opcode);
+            }
+            if cpu.pc == pc {
+                return Halt::Idle;
+            }
+        }
+    }
+}
+
+/// Ultimate stand-in running programs on a built-in 6502 core
+pub struct Emulator {
+    machine: RefCell<Machine>,
+    cycle_limit: u64,
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::with_cycle_limit(DEFAULT_CYCLE_LIMIT)
+    }
+}
+
+impl Emulator {
+    /// Create new emulator that stops programs after `cycle_limit` cycles
+    pub fn with_cycle_limit(cycle_limit: u64) -> Self {
+        Self {
+            machine: RefCell::new(Machine::new()),
+            cycle_limit,
+        }
+    }
+
+    /// Current CPU registers
+    pub fn cpu(&self) -> Cpu {
+        self.machine.borrow().cpu
+    }
+
+    /// Call machine code at `address` and run until it returns or stops
+    pub fn call(&self, address: u16) -> Halt {
+        let mut machine = self.machine.borrow_mut();
+        let halt = machine.call(address, self.cycle_limit);
+        let cpu = machine.cpu;
+        let message = format!(
+            "Program at {address:#06x} stopped after {} cycles ({halt}): A={:#04x} X={:#04x} Y={:#04x} PC={:#06x} $D7FF={:#04x}",
+            cpu.cycles, cpu.a, cpu.x, cpu.y, cpu.pc, machine.ram[DEBUG_REGISTER as usize]
+        );
+        match halt {
+            Halt::Illegal(_) => warn!("{message}"),
+            _ => info!("{message}"),
+        }
+        halt
+    }
+
+    /// Start the program in memory as BASIC `RUN` would, provided it begins with `SYS`
+    fn run_basic(&self) -> Result<Halt> {
+        let address = basic_sys_address(&self.machine.borrow().ram[BASIC_START as usize..])
+            .ok_or_else(|| {
+                Error::invalid("only BASIC programs starting with SYS can run offline")
+            })?;
+        Ok(self.call(address))
+    }
+}
+
+impl Ultimate for Emulator {
+    fn read_mem(&self, address: u16, length: u16) -> Result<Vec<u8>> {
+        check_address_overflow(address, length)?;
+        let start = address as usize;
+        Ok(self.machine.borrow().ram[start..start + length as usize].to_vec())
+    }
+
+    fn write_mem(&self, address: u16, data: &[u8]) -> Result<()> {
+        check_data_overflow(address, data.len())?;
+        let start = address as usize;
+        self.machine.borrow_mut().ram[start..start + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    fn reset(&self) -> Result<()> {
+        self.machine.borrow_mut().reset();
+        Ok(())
+    }
+
+    fn info(&self) -> Result<DeviceInfo> {
+        Ok(DeviceInfo {
+            product: "Emulator".to_string(),
+            firmware_version: env!("CARGO_PKG_VERSION").to_string(),
+            fpga_version: "none".to_string(),
+            core_version: None,
+            hostname: "offline".to_string(),
+            unique_id: None,
+        })
+    }
+
+    fn load_prg(&self, data: &[u8]) -> Result<()> {
+        self.load_data(data, None).map(|_| ())
+    }
+
+    fn run_prg(&self, data: &[u8]) -> Result<()> {
+        let (address, _) = self.load_data(data, None)?;
+        if address == BASIC_START {
+            self.run_basic()?;
+        } else {
+            self.call(address);
+        }
+        Ok(())
+    }
+
+    fn reboot(&self) -> Result<()> {
+        self.reset()
+    }
+
+    /// Nothing runs between calls, so pausing has no effect
+    fn pause(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn resume(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn read_debugreg(&self) -> Result<u8> {
+        Ok(self.machine.borrow().ram[DEBUG_REGISTER as usize])
+    }
+
+    fn write_debugreg(&self, value: u8) -> Result<()> {
+        self.machine.borrow_mut().ram[DEBUG_REGISTER as usize] = value;
+        Ok(())
+    }
+
+    /// Fill the keyboard buffer like the device does, then act on `RUN` and `SYS` lines
+    fn type_text(&self, s: &str) -> Result<()> {
+        for chunk in request::type_text(s) {
+            for (address, data) in chunk {
+                self.write_mem(address, &data)?;
+            }
+        }
+        for line in s.lines().map(|line| line.trim().to_lowercase()) {
+            if line == "run" {
+                self.run_basic()?;
+            } else if let Some(address) = line.strip_prefix("sys") {
+                let address = address
+                    .trim()
+                    .parse()
+                    .map_err(|_| Error::invalid(format!("invalid SYS address: {address}")))?;
+                self.call(address);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// PRG with `10 SYS2061` followed by the given machine code
+    fn basic_prg(code: &[u8]) -> Vec<u8> {
+        let mut prg = vec![
+            0x01, 0x08, 0x0b, 0x08, 0x0a, 0x00, 0x9e, 0x32, 0x30, 0x36, 0x31, 0x00, 0x00, 0x00,
+        ];
+        prg.extend_from_slice(code);
+        prg
+    }
+
+    #[test]
+    fn test_run_basic_prg() {
+        let emulator = Emulator::default();
+        // ldx #$00; loop: inx; stx $d7ff; cpx #$10; bne loop; rts
+        let prg = basic_prg(&[
+            0xa2, 0x00, 0xe8, 0x8e, 0xff, 0xd7, 0xe0, 0x10, 0xd0, 0xf8, 0x60,
+        ]);
+        emulator.run_prg(&prg).unwrap();
+        assert_eq!(emulator.read_debugreg().unwrap(), 0x10);
+        assert_eq!(emulator.cpu().pc, RETURN_ADDRESS);
+    }
+
+    #[test]
+    fn test_kernal_calls_return() {
+        let emulator = Emulator::default();
+        // lda #$41; jsr $ffd2; sta $0400; rts
+        let code = [0xa9, 0x41, 0x20, 0xd2, 0xff, 0x8d, 0x00, 0x04, 0x60];
+        emulator.write_mem(0xc000, &code).unwrap();
+        assert_eq!(emulator.call(0xc000), Halt::Returned);
+        assert_eq!(emulator.read_mem(0x0400, 1).unwrap(), [0x41]);
+    }
+
+    #[test]
+    fn test_halts() {
+        let emulator = Emulator::with_cycle_limit(1000);
+        emulator.write_mem(0xc000, &[0x4c, 0x00, 0xc0]).unwrap(); // jmp *
+        assert_eq!(emulator.call(0xc000), Halt::Idle);
+        emulator.write_mem(0xc000, &[0xea, 0x00]).unwrap(); // nop; brk
+        assert_eq!(emulator.call(0xc000), Halt::Break);
+        emulator.write_mem(0xc000, &[0x02]).unwrap();
+        assert_eq!(emulator.call(0xc000), Halt::Illegal(0x02));
+        // loop: inc $d020; jmp loop
+        emulator
+            .write_mem(0xc000, &[0xee, 0x20, 0xd0, 0x4c, 0x00, 0xc0])
+            .unwrap();
+        assert_eq!(emulator.call(0xc000), Halt::CycleLimit);
+    }
+
+    #[test]
+    fn test_type_text() {
+        let emulator = Emulator::default();
+        emulator
+            .load_prg(&basic_prg(&[0xee, 0xff, 0xd7, 0x60]))
+            .unwrap();
+        emulator.type_text("run\n").unwrap();
+        emulator.type_text("sys 2061\n").unwrap();
+        assert_eq!(emulator.read_debugreg().unwrap(), 2);
+        assert_eq!(emulator.read_mem(0xc6, 1).unwrap(), [9]);
+    }
+}
@@ -0,0 +1,103 @@
+//! # Error handling
+
+use std::fmt::Display;
+
+/// Errors returned by the library
+///
+/// Lets callers tell an unreachable device apart from e.g. a wrong password
+/// or a command that is not available on the connected product.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Device could not be reached or the connection failed
+    #[error("network error: {0}")]
+    Network(#[source] reqwest::Error),
+    /// Access denied (HTTP 403)
+    #[error("access denied: check password or device settings")]
+    Auth,
+    /// Command not available on this device or firmware (HTTP 501)
+    #[error("command unavailable on this Ultimate device")]
+    Unsupported,
+    /// Command needs another product or newer firmware than the connected device has
+    #[error("{0}")]
+    Incompatible(String),
+    /// Device rejected the request with the given HTTP status and error messages
+    #[error("request failed with status {status}{}", format_device_errors(.errors))]
+    Device {
+        /// HTTP status code
+        status: u16,
+        /// Messages from the `errors` array in the JSON response
+        errors: Vec<String>,
+    },
+    /// Invalid argument, e.g. an address range that overflows
+    #[error("{0}")]
+    InvalidArgument(String),
+    /// Machine is not in the state required by the command
+    #[error("{0}")]
+    NotReady(String),
+    /// Operation did not complete in time
+    #[error("timeout: {0}")]
+    Timeout(String),
+    /// Unexpected or malformed data
+    #[error("decode error: {0}")]
+    Decode(String),
+    /// File or socket error
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Result type used throughout the library
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+fn format_device_errors(errors: &[String]) -> String {
+    if errors.is_empty() {
+        String::new()
+    } else {
+        format!(": {}", errors.join("; "))
+    }
+}
+
+impl Error {
+    /// Invalid argument error from anything printable
+    pub(crate) fn invalid<T: Display>(message: T) -> Self {
+        Self::InvalidArgument(message.to_string())
+    }
+
+    /// Decode error from anything printable
+    pub(crate) fn decode<T: Display>(message: T) -> Self {
+        Self::Decode(message.to_string())
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_decode() {
+            Self::decode(err)
+        } else {
+            Self::Network(err)
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Self::decode(err)
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(err: toml::de::Error) -> Self {
+        Self::decode(err)
+    }
+}
+
+impl From<toml::ser::Error> for Error {
+    fn from(err: toml::ser::Error) -> Self {
+        Self::decode(err)
+    }
+}
+
+impl From<reqwest::header::InvalidHeaderValue> for Error {
+    fn from(err: reqwest::header::InvalidHeaderValue) -> Self {
+        Self::invalid(format!("invalid password: {err}"))
+    }
+}
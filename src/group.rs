@@ -0,0 +1,118 @@
+//! # Parallel control of several devices
+//!
+//! [`RestGroup`] sends the same call to a number of devices at once, e.g. to
+//! start a demo on every machine at a party or to reset a test rack, and
+//! collects the outcome for each device.
+//!
+//! # Examples
+//! ~~~ rust, ignore
+//! use ultimate64::{Rest, RestGroup};
+//! let mut group = RestGroup::default();
+//! group.add("left", Rest::new(&"192.168.1.64".parse()?, None)?);
+//! group.add("right", Rest::new(&"192.168.1.65".parse()?, None)?);
+//! for (name, result) in group.run_prg(&std::fs::read("demo.prg")?) {
+//!     println!("{name}: {result:?}");
+//! }
+//! ~~~
+
+use crate::{drives::MountMode, Rest, Result};
+use std::{panic, path::Path, thread};
+
+/// Named devices that are controlled together
+#[derive(Default)]
+pub struct RestGroup {
+    devices: Vec<(String, Rest)>,
+}
+
+impl RestGroup {
+    /// Add device under `name`, which identifies it in the results
+    pub fn add<S: Into<String>>(&mut self, name: S, rest: Rest) {
+        self.devices.push((name.into(), rest));
+    }
+
+    /// Number of devices
+    pub fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    /// True if the group has no devices
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// Device names in the order they were added
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.devices.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Call `f` with name and client of every device, each in its own thread
+    ///
+    /// Results are returned in the order the devices were added.
+    pub fn fan_out<T, F>(&self, f: F) -> Vec<(&str, T)>
+    where
+        T: Send,
+        F: Fn(&str, &Rest) -> T + Sync,
+    {
+        thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .devices
+                .iter()
+                .map(|(name, rest)| {
+                    let f = &f;
+                    (name.as_str(), scope.spawn(move || f(name, rest)))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|(name, handle)| {
+                    let result = handle.join().unwrap_or_else(|e| panic::resume_unwind(e));
+                    (name, result)
+                })
+                .collect()
+        })
+    }
+
+    /// Reset all machines
+    pub fn reset(&self) -> Vec<(&str, Result<()>)> {
+        self.fan_out(|_, rest| rest.reset())
+    }
+
+    /// Reboot all devices
+    pub fn reboot(&self) -> Vec<(&str, Result<()>)> {
+        self.fan_out(|_, rest| rest.reboot())
+    }
+
+    /// Load and run PRG on all machines
+    pub fn run_prg(&self, data: &[u8]) -> Vec<(&str, Result<()>)> {
+        self.fan_out(|_, rest| rest.run_prg(data))
+    }
+
+    /// Start cartridge image on all machines
+    pub fn run_crt(&self, data: &[u8]) -> Vec<(&str, Result<()>)> {
+        self.fan_out(|_, rest| rest.run_crt(data))
+    }
+
+    /// Type text on all machines
+    pub fn type_text(&self, s: &str) -> Vec<(&str, Result<()>)> {
+        self.fan_out(|_, rest| crate::Ultimate::type_text(rest, s))
+    }
+
+    /// Upload and mount disk image on all devices
+    pub fn mount_disk_image<P: AsRef<Path> + Sync>(
+        &self,
+        path: P,
+        drive: &str,
+        mode: MountMode,
+        run: bool,
+    ) -> Vec<(&str, Result<()>)> {
+        self.fan_out(|_, rest| rest.mount_disk_image(&path, drive.to_string(), mode.clone(), run))
+    }
+}
+
+impl FromIterator<(String, Rest)> for RestGroup {
+    fn from_iter<I: IntoIterator<Item = (String, Rest)>>(iter: I) -> Self {
+        Self {
+            devices: iter.into_iter().collect(),
+        }
+    }
+}
@@ -3,28 +3,60 @@
 //! hardware using the
 //! [REST API](https://1541u-documentation.readthedocs.io/en/latest/api/api_calls.html).
 //!
+//! Enable the `async` feature for the non-blocking `AsyncRest` client, and
+//! the `mock` feature for the mock device used in tests.
+//!
 
+#[cfg(feature = "async")]
+pub use crate::async_rest::AsyncRest;
+pub use crate::backend::Ultimate;
+pub use crate::error::{Error, Result};
+pub use crate::group::RestGroup;
 use crate::{
-    auxiliary::check_address_overflow,
-    drives::{DiskImageType, Drive, DriveList},
-    petscii::Petscii,
+    auxiliary::{check_address_overflow, check_data_overflow},
+    capabilities::{Capabilities, Feature},
+    config::{
+        CategoryList, ConfigCategory, ConfigItem, ConfigResponse, ConfigValue, Configuration,
+    },
+    drives::{DiskImageType, Drive, DriveId, DriveList, DriveType},
+    request::{Payload, Request, Retry, Target, Transfer},
+    snapshot::MachineSnapshot,
 };
-use anyhow::{anyhow, bail, ensure, Ok, Result};
 use clap::ValueEnum;
 use core::fmt::Display;
 use log::{debug, warn};
-use reqwest::{
-    blocking::{Body, Client, Response},
-    header::{HeaderMap, HeaderValue},
-    StatusCode,
+use reqwest::blocking::{multipart, Client, Response};
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{mpsc, Mutex, OnceLock},
+    thread::{self, sleep},
+    time::{Duration, Instant},
 };
-use std::{collections::HashMap, path::Path, thread::sleep, time::Duration};
 use url::Host;
 
+#[cfg(feature = "async")]
+pub mod async_rest;
 pub mod auxiliary;
+pub mod backend;
+pub mod capabilities;
+pub mod config;
+pub mod cpu;
+pub mod discovery;
 pub mod drives;
+pub mod emulator;
+pub mod error;
+pub mod group;
+#[cfg(feature = "mock")]
+pub mod mock;
 pub mod petscii;
+mod request;
+pub mod search;
+pub mod settings;
+pub mod snapshot;
 pub mod vicstream;
+pub mod vsf;
+pub mod watch;
 
 /// Ultimate-64 and Ultimate-II device information
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize, PartialEq, Eq)]
@@ -89,6 +121,143 @@ impl Display for StreamType {
     }
 }
 
+/// Default user agent sent with every request
+const USER_AGENT: &str = concat!("ultimate64/", env!("CARGO_PKG_VERSION"));
+
+/// Builder for [`Rest`] and `AsyncRest` with connection settings
+///
+/// IPv6 hosts are supported, e.g. `Host::parse("[fe80::1]")`.
+///
+/// # Examples
+/// ~~~ rust, ignore
+/// use ultimate64::RestBuilder;
+/// let ultimate = RestBuilder::new(&Host::parse("192.168.1.10")?)
+///     .connect_timeout(Duration::from_secs(2))
+///     .retries(5)
+///     .build()?;
+/// ~~~
+#[derive(Debug, Clone)]
+pub struct RestBuilder {
+    host: Host,
+    port: Option<u16>,
+    password: Option<String>,
+    connect_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+    retry: Retry,
+    transfer: Transfer,
+    user_agent: String,
+}
+
+impl RestBuilder {
+    /// New builder for `host` with default settings and no retries
+    pub fn new(host: &Host) -> Self {
+        Self {
+            host: host.clone(),
+            port: None,
+            password: None,
+            connect_timeout: None,
+            timeout: None,
+            retry: Retry {
+                retries: 0,
+                backoff: Duration::from_millis(500),
+            },
+            transfer: Transfer::DEFAULT,
+            user_agent: USER_AGENT.to_string(),
+        }
+    }
+
+    /// TCP port of the REST API (default: 80)
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = (port != 80).then_some(port);
+        self
+    }
+
+    /// Network password, if set on the device
+    pub fn password(mut self, password: Option<String>) -> Self {
+        self.password = password;
+        self
+    }
+
+    /// Timeout for establishing a connection
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Timeout for a complete request, from connecting until the response is read
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Number of times to retry reads and state-setting requests that fail with a network error
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retry.retries = retries;
+        self
+    }
+
+    /// Delay before the first retry; doubled for each following retry (default: 500 ms)
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.retry.backoff = backoff;
+        self
+    }
+
+    /// Largest number of bytes read or written by a single memory request (default: 4096)
+    pub fn chunk_size(mut self, chunk_size: u16) -> Self {
+        self.transfer.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Number of connections used in parallel for large memory transfers (default: 1)
+    pub fn connections(mut self, connections: usize) -> Self {
+        self.transfer.connections = connections.max(1);
+        self
+    }
+
+    /// User agent sent with every request (default: `ultimate64/<version>`)
+    pub fn user_agent<S: Into<String>>(mut self, user_agent: S) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    fn target(&self) -> Result<Target> {
+        let mut target = Target::new(&self.host, self.port, self.password.clone())?;
+        target.retry = self.retry;
+        target.transfer = self.transfer;
+        Ok(target)
+    }
+
+    /// HTTP client with the configured timeouts and user agent
+    pub(crate) fn http_client(&self) -> Result<Client> {
+        let mut client = Client::builder().user_agent(&self.user_agent);
+        if let Some(timeout) = self.connect_timeout {
+            client = client.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeout {
+            client = client.timeout(timeout);
+        }
+        Ok(client.build()?)
+    }
+
+    /// Create blocking client
+    pub fn build(self) -> Result<Rest> {
+        Ok(Rest::from_parts(self.http_client()?, self.target()?))
+    }
+
+    /// Create asynchronous client
+    #[cfg(feature = "async")]
+    pub fn build_async(self) -> Result<AsyncRest> {
+        let mut client = reqwest::Client::builder().user_agent(&self.user_agent);
+        if let Some(timeout) = self.connect_timeout {
+            client = client.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeout {
+            client = client.timeout(timeout);
+        }
+        Ok(AsyncRest::from_parts(client.build()?, self.target()?))
+    }
+}
+
 /// Communication with Ultimate series using
 /// the [REST API](https://1541u-documentation.readthedocs.io/en/latest/api/api_calls.html)
 ///
@@ -102,107 +271,140 @@ impl Display for StreamType {
 pub struct Rest {
     /// HTTP client
     client: Client,
-    /// Header
-    url_prefix: String,
-    /// Headers
-    headers: HeaderMap,
+    /// URL prefix and headers
+    target: Target,
+    /// Capabilities, probed on first use
+    capabilities: OnceLock<Capabilities>,
 }
 
 impl Rest {
+    /// Client and target prepared by [`RestBuilder`]
+    pub(crate) fn from_parts(client: Client, target: Target) -> Self {
+        Self {
+            client,
+            target,
+            capabilities: OnceLock::new(),
+        }
+    }
+
     /// Create new Rest instance
     pub fn new(host: &Host, password: Option<String>) -> Result<Self> {
-        let mut headers = HeaderMap::default();
-        if let Some(pw) = password {
-            headers.insert("X-password", HeaderValue::from_str(pw.as_str())?);
-        }
+        RestBuilder::new(host).password(password).build()
+    }
 
-        Ok(Self {
-            client: Client::new(),
-            url_prefix: format!("http://{host}/v1"),
-            headers,
-        })
+    /// Create new Rest instance for a device listening on a non-default port
+    pub fn with_port(host: &Host, port: u16, password: Option<String>) -> Result<Self> {
+        RestBuilder::new(host).port(port).password(password).build()
     }
 
-    /// Check sanity of response
-    fn check_response(response: &Response) -> Result<()> {
-        // Handle a few specific status codes
-        match response.status() {
-            StatusCode::FORBIDDEN => bail!("access denied: check password or device settings"),
-            StatusCode::NOT_IMPLEMENTED => bail!("command unavailable on this Ultimate device"),
-            _ => {}
+    /// Send request, retrying idempotent requests on network errors if enabled
+    fn send(&self, request: Request) -> Result<Response> {
+        let retry = self.target.retry;
+        if !retry.applies_to(&request) {
+            return self.send_once(request);
+        }
+        let mut attempt = 0;
+        loop {
+            match self.send_once(request.clone()) {
+                Err(error) => match retry.delay(attempt, &error) {
+                    Some(delay) => {
+                        warn!("{error}; retrying in {delay:?}");
+                        sleep(delay);
+                        attempt += 1;
+                    }
+                    None => return Err(error),
+                },
+                result => return result,
+            }
         }
-        ensure!(
-            response.status().is_success(),
-            "request failed with status: {}",
-            response.status()
-        );
-        Ok(())
     }
 
-    /// HTTP PUT request
-    fn put(&self, path: &str) -> Result<Response> {
-        let url = format!("{}/{}", self.url_prefix, path);
-        let response = self.client.put(url).headers(self.headers.clone()).send()?;
-        Self::check_response(&response)?;
-        Ok(response)
+    /// Send request once and check sanity of response
+    fn send_once(&self, request: Request) -> Result<Response> {
+        let builder = self
+            .client
+            .request(request.method.clone(), self.target.url(&request))
+            .headers(self.target.headers.clone());
+        let builder = match request.payload {
+            Payload::Empty => builder,
+            Payload::Bytes(data) => builder.body(data),
+            Payload::Json(data) => builder
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(data),
+            Payload::Form {
+                file_name,
+                data,
+                fields,
+            } => {
+                let part = multipart::Part::bytes(data).file_name(file_name);
+                let form = fields
+                    .into_iter()
+                    .fold(multipart::Form::new().part("file", part), |form, (k, v)| {
+                        form.text(k, v)
+                    });
+                builder.multipart(form)
+            }
+        };
+        let response = builder.send()?;
+        let status = response.status();
+        if status.is_success() {
+            Ok(response)
+        } else {
+            Err(request::status_error(
+                status,
+                &response.text().unwrap_or_default(),
+            ))
+        }
     }
 
-    /// HTTP GET request
-    fn get(&self, path: &str) -> Result<Response> {
-        let url = format!("{}/{}", self.url_prefix, path);
-        let response = self.client.get(url).headers(self.headers.clone()).send()?;
-        Self::check_response(&response)?;
-        Ok(response)
+    /// Send request and discard response
+    fn execute(&self, request: Request) -> Result<()> {
+        self.send(request)?;
+        Ok(())
     }
 
-    /// HTTP POST request with body
-    fn post<T: Into<Body>>(&self, path: &str, body: T) -> Result<Response> {
-        let url = format!("{}/{}", self.url_prefix, path);
-        let response = self
-            .client
-            .post(url)
-            .body(body)
-            .headers(self.headers.clone())
-            .send()?;
-        Self::check_response(&response)?;
-        Ok(response)
+    /// Products and firmware features of the device, probed once and cached
+    pub fn capabilities(&self) -> Result<&Capabilities> {
+        if let Some(capabilities) = self.capabilities.get() {
+            return Ok(capabilities);
+        }
+        let version = self.send(request::version())?.json()?;
+        let capabilities = request::decode_capabilities(version, self.info())?;
+        Ok(self.capabilities.get_or_init(|| capabilities))
+    }
+
+    /// Fail with [`Error::Incompatible`] unless the device supports `feature`
+    pub fn require(&self, feature: Feature) -> Result<()> {
+        self.capabilities()?.require(feature)
     }
 
     /// Get device information
     pub fn info(&self) -> Result<DeviceInfo> {
-        let body = self.get("info")?.text()?;
+        let body = self.send(request::info())?.text()?;
         Ok(serde_json::from_str(&body)?)
     }
 
     /// Get version
     pub fn version(&self) -> Result<String> {
-        let response = self.get("version")?;
-        let body = response.text()?;
-        Ok(body)
+        Ok(self.send(request::version())?.text()?)
     }
 
     /// Get drives
     pub fn drives(&self) -> Result<String> {
-        let response = self.get("drives")?;
-        let body = response.text()?;
-        Ok(body)
+        Ok(self.send(request::drives())?.text()?)
     }
 
     /// Load PRG bytes into memory - do NOT run.
     /// The machine resets, and loads the attached program into memory using DMA.
     pub fn load_prg(&self, prg_data: &[u8]) -> Result<()> {
-        debug!("Load PRG file of {} bytes", prg_data.len());
-        self.post("runners:load_prg", prg_data.to_vec())?;
-        Ok(())
+        self.execute(request::load_prg(prg_data))
     }
 
     /// Load and run PRG bytes into memory
     ///
     /// The machine resets, and loads the attached program into memory using DMA.
     pub fn run_prg(&self, data: &[u8]) -> Result<()> {
-        debug!("Run PRG file of {} bytes", data.len());
-        self.post("runners:run_prg", data.to_vec())?;
-        Ok(())
+        self.execute(request::run_prg(data))
     }
 
     /// Start supplied cartridge file
@@ -211,177 +413,233 @@ impl Rest {
     /// The machine resets, with the attached cartridge active.
     /// It does not alter the configuration of the Ultimate.
     pub fn run_crt(&self, data: &[u8]) -> Result<()> {
-        debug!("Run CRT file of {} bytes", data.len());
-        self.post("runners:run_crt", data.to_vec())?;
-        Ok(())
+        self.execute(request::run_crt(data))
+    }
+
+    /// Load PRG file stored on the Ultimate's USB or SD storage - do NOT run.
+    pub fn load_remote_prg(&self, path: &str) -> Result<()> {
+        self.execute(request::load_remote_prg(path))
+    }
+
+    /// Load and run PRG file stored on the Ultimate's USB or SD storage
+    pub fn run_remote_prg(&self, path: &str) -> Result<()> {
+        self.execute(request::run_remote_prg(path))
+    }
+
+    /// Start cartridge file stored on the Ultimate's USB or SD storage
+    pub fn run_remote_crt(&self, path: &str) -> Result<()> {
+        self.execute(request::run_remote_crt(path))
     }
 
     /// Emulate pressing the menu button
     pub fn menu(&self) -> Result<()> {
-        debug!("Emulating menu button press");
-        self.put("machine:menu_button")?;
-        Ok(())
+        self.execute(request::menu())
     }
 
     /// Reset machine
     pub fn reset(&self) -> Result<()> {
-        debug!("Reset machine");
-        self.put("machine:reset")?;
-        Ok(())
+        self.execute(request::reset())
     }
     /// Reboot machine
     pub fn reboot(&self) -> Result<()> {
-        debug!("Reboot machine");
-        self.put("machine:reboot")?;
-        Ok(())
+        self.execute(request::reboot())
+    }
+
+    /// Poll the device until it answers, e.g. after a reboot or power-on
+    ///
+    /// Fails with [`Error::Timeout`] if the device is still unreachable after `timeout`.
+    pub fn wait_online(&self, timeout: Duration) -> Result<()> {
+        let start = Instant::now();
+        loop {
+            match self.version() {
+                Ok(_) => return Ok(()),
+                Err(error) if request::is_offline(&error) => {
+                    if start.elapsed() >= timeout {
+                        return Err(request::online_timeout(timeout, error));
+                    }
+                    debug!("Waiting for device: {error}");
+                }
+                Err(error) => return Err(error),
+            }
+            sleep(request::ONLINE_POLL_INTERVAL);
+        }
+    }
+
+    /// Wait until the device is online and the C64 shows the BASIC READY prompt
+    ///
+    /// The prompt is detected in screen memory, so a screen showing `READY.`
+    /// from before a reset may be seen as ready.
+    pub fn wait_ready(&self, timeout: Duration) -> Result<()> {
+        let start = Instant::now();
+        self.wait_online(timeout)?;
+        let (address, length) = request::CURSOR_STATE;
+        loop {
+            let cursor_state = self.read_mem(address, length)?;
+            let screen = self.read_mem(request::SCREEN_ADDRESS, request::SCREEN_SIZE)?;
+            if request::screen_ready(&cursor_state, &screen) {
+                return Ok(());
+            }
+            if start.elapsed() >= timeout {
+                return Err(request::ready_timeout(timeout));
+            }
+            sleep(request::ONLINE_POLL_INTERVAL);
+        }
     }
 
     /// Pause machine
     pub fn pause(&self) -> Result<()> {
-        debug!("Pause machine");
-        self.put("machine:pause")?;
-        Ok(())
+        self.execute(request::pause())
     }
 
     /// Resume machine
     pub fn resume(&self) -> Result<()> {
-        debug!("Resume machine");
-        self.put("machine:resume")?;
-        Ok(())
+        self.execute(request::resume())
+    }
+
+    /// Pause, capture RAM and I/O chip registers, and resume
+    pub fn snapshot(&self) -> Result<MachineSnapshot> {
+        MachineSnapshot::capture(self)
+    }
+
+    /// Pause, write back a snapshot, and resume
+    pub fn restore_snapshot(&self, snapshot: &MachineSnapshot) -> Result<()> {
+        snapshot.restore(self)
     }
     /// Poweroff machine
     pub fn poweroff(&self) -> Result<()> {
-        debug!("Poweroff machine");
-        self.put("machine:poweroff")?;
-        Ok(())
+        self.execute(request::poweroff())
     }
 
-    /// Write data to memory using a POST request
-    pub fn write_mem(&self, address: u16, data: &[u8]) -> Result<()> {
-        check_address_overflow(address, data.len() as u16)?;
-        if matches!(address, 0 | 1) {
-            warn!("DMA cannot access internal CPU registers at address 0 and 1");
-        }
-        let path = format!("machine:writemem?address={address:x}");
-        self.post(&path, data.to_vec())?;
-        debug!("Wrote {} byte(s) to {:#06x}", data.len(), address);
-        Ok(())
+    /// Read the U64 debug register ($D7FF)
+    pub fn read_debugreg(&self) -> Result<u8> {
+        request::decode_debugreg(self.send(request::read_debugreg())?.json()?)
     }
 
-    /// Emulate keyboard input
-    ///
-    /// Done by injecting PETSCII bytes to the C64 input buffer.
-    pub fn type_text(&self, s: &str) -> Result<()> {
-        debug!("Emulating keyboard typing: {s}");
-        // From the C64 Programmers Reference Guide, page 315-316:
-        const KEYBOARD_LSTX: u16 = 0xc5; // Current key pressed (64 = no key pressed)
-        const KEYBOARD_NDX: u16 = 0xc6; // Number of characters in keyboard buffer
-        const KEYBOARD_BUFFER: u16 = 0x277; // Keyboard buffer queue (10 bytes)
-
-        ensure!(
-            self.basic_ready()?,
-            "cannot emulate typing as BASIC prompt is not ready"
-        );
-
-        // Convert string to PETSCII bytes
-        let petscii: Vec<u8> = s
-            .chars()
-            .map(|c| Petscii::from_str_lossy(&c.to_string())[0])
-            .collect();
-
-        // C64 input buffer is limited to 10 characters
-        for chunk in petscii.chunks(10) {
-            self.write_mem(KEYBOARD_LSTX, &[0, 0])?; // clear keyboard buffer
-            self.write_mem(KEYBOARD_BUFFER, chunk)?; // write PETSCII to buffer
-            self.write_mem(KEYBOARD_NDX, &[chunk.len() as u8])?; // trigger typing
-            sleep(Duration::from_millis(20)); // wait for C64 (may not be needed)
-        }
-        Ok(())
+    /// Write to the U64 debug register ($D7FF)
+    pub fn write_debugreg(&self, value: u8) -> Result<()> {
+        self.execute(request::write_debugreg(value))
     }
 
-    /// Read word (2 bytes) from memory and interpret as little endian
-    pub fn read_le_word(&self, address: u16) -> Result<u16> {
-        let bytes: [u8; 2] = self
-            .read_mem(address, 2)?
-            .try_into()
-            .map_err(|_| anyhow!("failed to read from {address:#06x}"))?;
-        Ok(u16::from_le_bytes(bytes))
+    /// Write data to memory using POST requests
+    pub fn write_mem(&self, address: u16, data: &[u8]) -> Result<()> {
+        self.write_mem_with_progress(address, data, |_, _| {})
     }
 
-    /// Check if BASIC prompt is active and accepts input
-    ///
-    /// Done by checking if the system vector at 0x0302 points the BASIN kernal routine.
-    #[allow(unused)]
-    fn basic_ready(&self) -> Result<bool> {
-        return Ok(true);
-        todo!("implement correct basic_ready check");
-        const BASIN_ADDR: u16 = 0xa7ae; // BASIC input routine in Kernal ROM
-        const VECTOR_ADDR: u16 = 0x0302; // System vector
-        let word = self.read_le_word(VECTOR_ADDR)?;
-        debug!("Word at {VECTOR_ADDR:#06x} is {word:#06x}");
-        ensure!(
-            word != 0,
-            "BASIC prompt is not ready, vector at {VECTOR_ADDR:#06x} is zero"
-        );
-        Ok(self.read_le_word(VECTOR_ADDR)? == BASIN_ADDR)
+    /// Write data to memory in chunks, calling `progress` with the number of bytes written so far and in total
+    pub fn write_mem_with_progress(
+        &self,
+        address: u16,
+        data: &[u8],
+        progress: impl FnMut(usize, usize),
+    ) -> Result<()> {
+        check_data_overflow(address, data.len())?;
+        self.transfer_chunks(address, data.len(), progress, |chunk_address, length| {
+            let offset = chunk_address.wrapping_sub(address) as usize;
+            self.execute(request::write_mem(
+                chunk_address,
+                &data[offset..offset + length as usize],
+            )?)
+        })
     }
 
     /// Read `length` bytes from `address`
     pub fn read_mem(&self, address: u16, length: u16) -> Result<Vec<u8>> {
+        self.read_mem_with_progress(address, length, |_, _| {})
+    }
+
+    /// Read memory in chunks, calling `progress` with the number of bytes read so far and in total
+    pub fn read_mem_with_progress(
+        &self,
+        address: u16,
+        length: u16,
+        progress: impl FnMut(usize, usize),
+    ) -> Result<Vec<u8>> {
         check_address_overflow(address, length)?;
-        if matches!(address, 0 | 1) {
-            warn!("Warning: DMA cannot access internal CPU registers at address 0 and 1");
-        }
-        let path = format!("machine:readmem?address={address:x}&length={length}");
-        let bytes = self.get(path.as_str())?.bytes()?.to_vec();
-        debug!("Read {length} byte(s) from {address:#06x}");
-        Ok(bytes)
+        let data = Mutex::new(vec![0; length as usize]);
+        self.transfer_chunks(
+            address,
+            length as usize,
+            progress,
+            |chunk_address, length| {
+                let bytes = self
+                    .send(request::read_mem(chunk_address, length)?)?
+                    .bytes()?;
+                request::check_read_length(chunk_address, length, &bytes)?;
+                let offset = chunk_address.wrapping_sub(address) as usize;
+                data.lock().unwrap_or_else(|e| e.into_inner())[offset..offset + bytes.len()]
+                    .copy_from_slice(&bytes);
+                Ok(())
+            },
+        )?;
+        Ok(data.into_inner().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Call `transfer` for each chunk of `length` bytes from `address`, spread over the configured connections
+    ///
+    /// `progress` is called from the calling thread as chunks complete; the first error stops the transfer.
+    fn transfer_chunks<F>(
+        &self,
+        address: u16,
+        length: usize,
+        mut progress: impl FnMut(usize, usize),
+        transfer: F,
+    ) -> Result<()>
+    where
+        F: Fn(u16, u16) -> Result<()> + Sync,
+    {
+        let chunks = self.target.transfer.chunks(address, length);
+        let workers = self.target.transfer.connections.min(chunks.size_hint().0);
+        let chunks = Mutex::new(chunks);
+        let next_chunk = || chunks.lock().unwrap_or_else(|e| e.into_inner()).next();
+        let (sender, receiver) = mpsc::channel();
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                let sender = sender.clone();
+                let (next_chunk, transfer) = (&next_chunk, &transfer);
+                scope.spawn(move || {
+                    while let Some((chunk_address, chunk_length)) = next_chunk() {
+                        let result = transfer(chunk_address, chunk_length).map(|_| chunk_length);
+                        let failed = result.is_err();
+                        if sender.send(result).is_err() || failed {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+            let mut done = 0;
+            for result in receiver {
+                done += result? as usize;
+                progress(done, length);
+            }
+            Ok(())
+        })
     }
 
     /// Play SID file - if no `songnr` is provided, the default song is played.
     pub fn sid_play(&self, siddata: &[u8], songnr: Option<u8>) -> Result<()> {
-        let path = match songnr {
-            Some(songnr) => format!("runners:sidplay?songnr={songnr}"),
-            None => "runners:sidplay".to_string(),
-        };
-        self.post(&path, siddata.to_vec())?;
-        Ok(())
+        self.execute(request::sid_play(siddata, songnr))
     }
 
     /// Play amiga MOD file
     pub fn mod_play(&self, moddata: &[u8]) -> Result<()> {
-        self.post("runners:modplay", moddata.to_vec())?;
-        Ok(())
+        self.execute(request::mod_play(moddata))
     }
 
-    /// Load data into memory using either a custom address, or deduce the
-    /// load address from the first two bytes of the data (little endian).
-    /// In the case of the latter, the first two bytes are not written to memory.
-    /// Returns the load address and the number of bytes written.
-    pub fn load_data(&self, data: &[u8], address: Option<u16>) -> Result<(u16, usize)> {
-        match address {
-            Some(address) => {
-                self.write_mem(address, data)?;
-                Ok((address, data.len()))
-            }
-            None => {
-                let load_address = auxiliary::extract_load_address(data)?;
-                self.write_mem(load_address, &data[2..])?; // skip first two bytes
-                Ok((load_address, data.len() - 2))
-            }
-        }
+    /// Play SID file stored on the Ultimate's USB or SD storage
+    pub fn sid_play_remote(&self, path: &str, songnr: Option<u8>) -> Result<()> {
+        self.execute(request::sid_play_remote(path, songnr))
+    }
+
+    /// Play amiga MOD file stored on the Ultimate's USB or SD storage
+    pub fn mod_play_remote(&self, path: &str) -> Result<()> {
+        self.execute(request::mod_play_remote(path))
     }
 
     /// Get drive list
     pub fn drive_list(&self) -> Result<HashMap<String, Drive>> {
-        let response = self.get("drives")?;
-        let nested: DriveList = response.json()?;
-        let drives = nested
-            .drives
-            .iter()
-            .flat_map(|m| m.iter().map(|(name, drive)| (name.clone(), drive.clone())))
-            .collect();
-        Ok(drives)
+        let nested: DriveList = self.send(request::drives())?.json()?;
+        Ok(request::decode_drive_list(nested))
     }
 
     /// Mount disk image
@@ -395,51 +653,253 @@ impl Rest {
         mount_mode: drives::MountMode,
         run: bool,
     ) -> Result<()> {
-        let disktype = DiskImageType::from_file_name(&path)?;
-        let url = format!("{}/drives/{drive}:mount", self.url_prefix);
-        let form = reqwest::blocking::multipart::Form::new()
-            .file("file", path)
-            .map_err(|e| anyhow!("disk image error: {e}"))?
-            .text("mode", mount_mode.to_string())
-            .text("type", disktype.to_string());
-
-        let response = self
-            .client
-            .post(url)
-            .multipart(form)
-            .headers(self.headers.clone())
-            .send()?;
-
-        Self::check_response(&response)?;
-
-        // should not trigger by normal operation and indicates a problem
-        // with the request or the server
-        if response.status().is_client_error() {
-            bail!(
-                "disk mount error: {} - {}",
-                response.status(),
-                response.text().unwrap()
-            );
+        let path = path.as_ref();
+        DiskImageType::from_file_name(path)?;
+        // only the base name is sent; the device shows it in its file browser
+        let file_name = path
+            .file_name()
+            .map_or_else(|| path.to_string_lossy(), |name| name.to_string_lossy())
+            .to_string();
+        let data = std::fs::read(path)?;
+        self.execute(request::mount_disk_image(
+            file_name, data, &drive, mount_mode,
+        )?)?;
+        if run {
+            self.run_mounted_disk()?;
         }
-        // optionally reset and run the first program on the disk
-        // a short delay is needed to allow the reset to complete
+        Ok(())
+    }
+
+    /// Mount disk image already stored on the Ultimate's USB or SD storage
+    ///
+    /// Curl equivalent:
+    /// `curl -X PUT "192.168.68.81/v1/drives/a:mount?image=/Usb0/disk.d64&mode=readonly&type=d64"`
+    pub fn mount_remote_image(
+        &self,
+        path: &str,
+        drive: String,
+        mount_mode: drives::MountMode,
+        run: bool,
+    ) -> Result<()> {
+        self.execute(request::mount_remote_image(path, &drive, mount_mode)?)?;
         if run {
-            self.reset()?;
-            sleep(Duration::from_secs(3));
-            self.type_text("load\"*\",8,1\nrun\n")?;
+            self.run_mounted_disk()?;
         }
         Ok(())
     }
 
+    /// Upload custom ROM image (e.g. JiffyDOS) to drive
+    ///
+    /// The ROM size is validated against the current drive type.
+    /// Returns the drive description after loading, where `rom` names the active ROM.
+    pub fn load_drive_rom(&self, drive: DriveId, rom: &[u8]) -> Result<Drive> {
+        let status = self.drive(drive)?;
+        self.execute(request::load_drive_rom(drive, &status, rom)?)?;
+        self.drive(drive)
+    }
+
+    /// Turn drive on
+    pub fn drive_on(&self, drive: DriveId) -> Result<()> {
+        self.execute(request::drive_on(drive))
+    }
+
+    /// Turn drive off
+    pub fn drive_off(&self, drive: DriveId) -> Result<()> {
+        self.execute(request::drive_off(drive))
+    }
+
+    /// Reset drive
+    pub fn drive_reset(&self, drive: DriveId) -> Result<()> {
+        self.execute(request::drive_reset(drive))
+    }
+
+    /// Eject disk image from drive
+    pub fn drive_eject(&self, drive: DriveId) -> Result<()> {
+        self.execute(request::drive_eject(drive))
+    }
+
+    /// Unlink disk image from drive
+    ///
+    /// The image stays mounted, but changes are no longer written back to the file.
+    pub fn drive_unlink(&self, drive: DriveId) -> Result<()> {
+        self.execute(request::drive_unlink(drive))
+    }
+
+    /// Switch drive between 1541, 1571, and 1581 emulation
+    pub fn drive_set_mode(&self, drive: DriveId, mode: DriveType) -> Result<()> {
+        self.execute(request::drive_set_mode(drive, mode)?)
+    }
+
+    /// Create blank disk image on the Ultimate's USB or SD storage
+    ///
+    /// `tracks` is optional for D64 (35 or 40) and required for DNP (1-255);
+    /// it cannot be given for D71 and D81. GCR images (G64, G71) cannot be created.
+    pub fn create_disk_image(
+        &self,
+        path: &str,
+        kind: DiskImageType,
+        name: Option<&str>,
+        tracks: Option<u8>,
+    ) -> Result<()> {
+        self.execute(request::create_disk_image(path, kind, name, tracks)?)
+    }
+
     /// Start video, audio, or debug streaming
     pub fn start_stream(&self, host: &Host, port: u16, kind: StreamType) -> Result<()> {
-        self.put(&format!("streams/{kind}:start?ip={host}:{port}"))?;
-        Ok(())
+        self.execute(request::start_stream(host, port, kind))
     }
 
     /// Start video, audio, or debug streaming
     pub fn stop_stream(&self, kind: StreamType) -> Result<()> {
-        self.put(&format!("streams/{kind}:stop"))?;
-        Ok(())
+        self.execute(request::stop_stream(kind))
+    }
+
+    /// List configuration categories
+    pub fn config_categories(&self) -> Result<Vec<String>> {
+        let list: CategoryList = self.send(request::config_categories())?.json()?;
+        Ok(list.categories)
+    }
+
+    /// Get all items and their current values in a configuration category
+    pub fn config_category(&self, category: &str) -> Result<ConfigCategory> {
+        let response: ConfigResponse<ConfigValue> =
+            self.send(request::config_category(category))?.json()?;
+        request::decode_config_category(response, category)
+    }
+
+    /// Get a single configuration item including its allowed values or range
+    pub fn config_item(&self, category: &str, item: &str) -> Result<ConfigItem> {
+        let response: ConfigResponse<ConfigItem> =
+            self.send(request::config_item(category, item))?.json()?;
+        request::decode_config_item(response, category, item)
+    }
+
+    /// Set a single configuration item
+    pub fn set_config_item(&self, category: &str, item: &str, value: &ConfigValue) -> Result<()> {
+        self.execute(request::set_config_item(category, item, value))
+    }
+
+    /// Set several configuration items, possibly in different categories, in a single request
+    pub fn set_config_items(&self, config: &Configuration) -> Result<()> {
+        self.execute(request::set_config_items(config)?)
+    }
+
+    /// Get all configuration categories with their current values
+    pub fn configuration(&self) -> Result<Configuration> {
+        self.config_categories()?
+            .into_iter()
+            .map(|category| {
+                let items = self.config_category(&category)?;
+                Ok((category, items))
+            })
+            .collect()
+    }
+
+    /// Apply configuration, only sending items that differ from the device
+    ///
+    /// Returns the items that were changed.
+    pub fn apply_configuration(&self, config: &Configuration) -> Result<Configuration> {
+        let changes = config::changed_items(&self.configuration()?, config);
+        if !changes.is_empty() {
+            self.set_config_items(&changes)?;
+        }
+        Ok(changes)
+    }
+
+    /// Save current configuration to flash so that it survives a power cycle
+    pub fn save_config_to_flash(&self) -> Result<()> {
+        self.execute(request::save_config_to_flash())
+    }
+
+    /// Restore configuration from flash, discarding unsaved changes
+    pub fn load_config_from_flash(&self) -> Result<()> {
+        self.execute(request::load_config_from_flash())
+    }
+
+    /// Reset configuration to factory defaults (not saved to flash)
+    pub fn reset_config_to_defaults(&self) -> Result<()> {
+        self.execute(request::reset_config_to_defaults())
+    }
+}
+
+impl Ultimate for Rest {
+    fn read_mem(&self, address: u16, length: u16) -> Result<Vec<u8>> {
+        Rest::read_mem(self, address, length)
+    }
+    fn write_mem(&self, address: u16, data: &[u8]) -> Result<()> {
+        Rest::write_mem(self, address, data)
+    }
+    fn read_mem_with_progress(
+        &self,
+        address: u16,
+        length: u16,
+        progress: &mut dyn FnMut(usize, usize),
+    ) -> Result<Vec<u8>> {
+        Rest::read_mem_with_progress(self, address, length, progress)
+    }
+    fn write_mem_with_progress(
+        &self,
+        address: u16,
+        data: &[u8],
+        progress: &mut dyn FnMut(usize, usize),
+    ) -> Result<()> {
+        Rest::write_mem_with_progress(self, address, data, progress)
+    }
+    fn reset(&self) -> Result<()> {
+        Rest::reset(self)
+    }
+    fn info(&self) -> Result<DeviceInfo> {
+        Rest::info(self)
+    }
+    fn version(&self) -> Result<String> {
+        Rest::version(self)
+    }
+    fn load_prg(&self, data: &[u8]) -> Result<()> {
+        Rest::load_prg(self, data)
+    }
+    fn run_prg(&self, data: &[u8]) -> Result<()> {
+        Rest::run_prg(self, data)
+    }
+    fn run_crt(&self, data: &[u8]) -> Result<()> {
+        Rest::run_crt(self, data)
+    }
+    fn sid_play(&self, data: &[u8], songnr: Option<u8>) -> Result<()> {
+        Rest::sid_play(self, data, songnr)
+    }
+    fn mod_play(&self, data: &[u8]) -> Result<()> {
+        Rest::mod_play(self, data)
+    }
+    fn menu(&self) -> Result<()> {
+        Rest::menu(self)
+    }
+    fn reboot(&self) -> Result<()> {
+        Rest::reboot(self)
+    }
+    fn pause(&self) -> Result<()> {
+        Rest::pause(self)
+    }
+    fn resume(&self) -> Result<()> {
+        Rest::resume(self)
+    }
+    fn poweroff(&self) -> Result<()> {
+        Rest::poweroff(self)
+    }
+    fn read_debugreg(&self) -> Result<u8> {
+        Rest::read_debugreg(self)
+    }
+    fn write_debugreg(&self, value: u8) -> Result<()> {
+        Rest::write_debugreg(self, value)
+    }
+    fn drive_list(&self) -> Result<HashMap<String, Drive>> {
+        Rest::drive_list(self)
+    }
+    fn mount_disk_image(
+        &self,
+        path: &Path,
+        drive: String,
+        mount_mode: drives::MountMode,
+        run: bool,
+    ) -> Result<()> {
+        Rest::mount_disk_image(self, path, drive, mount_mode, run)
     }
 }
@@ -1,23 +1,43 @@
-use anyhow::{bail, ensure, Result};
+use anyhow::{bail, ensure, Context, Result};
 use clap::builder::styling::{AnsiColor, Effects, Styles};
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use log::debug;
 use parse_int::parse;
 use ultimate64::{
     auxiliary,
-    drives::{self, Drive},
-    vicstream, Rest, StreamType,
+    capabilities::Feature,
+    config::{
+        self, ConfigCategory, ConfigDifference, ConfigItem, ConfigValue, Configuration,
+        ProfileFormat,
+    },
+    discovery::{self, Scan, Subnet},
+    drives::{self, Drive, DriveId, DriveType},
+    emulator::Emulator,
+    search::{self, Memory, Pattern},
+    settings::{DeviceProfile, Settings},
+    snapshot::{self, MachineSnapshot},
+    vicstream,
+    watch::{Region, Watcher},
+    DeviceInfo, Rest, RestBuilder, RestGroup, StreamType, Ultimate,
 };
 extern crate pretty_env_logger;
 use pretty_env_logger::env_logger::DEFAULT_FILTER_ENV;
 use prettytable::{format, Cell, Row, Table};
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fs;
+use std::io::{BufWriter, IsTerminal, Write};
+use std::net::{IpAddr, Ipv4Addr};
+use std::ops::RangeInclusive;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use url::{Host, Url};
 
-/// BASIC load address on C64
-const BASIC_LOAD_ADDR: u16 = 0x0801;
+/// Time for the device to go down, or the screen to clear, before waiting for it
+const SETTLE_DELAY: Duration = Duration::from_secs(2);
+
+/// Multicast address for streams unless set in the device profile
+const DEFAULT_STREAM_ADDRESS: Ipv4Addr = Ipv4Addr::new(239, 0, 1, 64);
 
 // Clap 4 colors: https://github.com/clap-rs/clap/issues/3234#issuecomment-1783820412
 fn styles() -> Styles {
@@ -30,7 +50,46 @@ fn styles() -> Styles {
 
 /// Helper function to determine if file has a disk image extension
 fn has_disk_image_extension<P: AsRef<Path>>(file: P) -> Result<()> {
-    drives::DiskImageType::from_file_name(file).map(|_| ())
+    drives::DiskImageType::from_file_name(file)?;
+    Ok(())
+}
+
+/// Parse `ITEM=VALUE` configuration assignment
+fn parse_assignment(s: &str) -> Result<(String, ConfigValue), String> {
+    s.split_once('=')
+        .map(|(item, value)| (item.trim().to_string(), ConfigValue::from(value.trim())))
+        .ok_or_else(|| format!("expected ITEM=VALUE, got: {s}"))
+}
+
+/// Prefix for files stored on the Ultimate's own USB or SD storage
+const DEVICE_PREFIX: &str = "device:";
+
+/// File either on the local machine or on the Ultimate's storage
+#[derive(Debug, Clone)]
+enum FileLocation {
+    /// Local file that is uploaded
+    Local(PathBuf),
+    /// Path on the device, e.g. `/Usb0/games/foo.d64`
+    Device(String),
+}
+
+impl FileLocation {
+    /// Path used for extension checks
+    fn path(&self) -> &Path {
+        match self {
+            Self::Local(path) => path,
+            Self::Device(path) => Path::new(path),
+        }
+    }
+}
+
+/// Parse file argument; paths starting with `device:` refer to the Ultimate's storage
+fn parse_file_location(s: &str) -> Result<FileLocation, String> {
+    match s.strip_prefix(DEVICE_PREFIX) {
+        Some(path) if path.starts_with('/') => Ok(FileLocation::Device(path.to_string())),
+        Some(path) => Err(format!("device path must be absolute, got: {path}")),
+        None => Ok(FileLocation::Local(PathBuf::from(s))),
+    }
 }
 
 /// A fictional versioning CLI
@@ -41,10 +100,15 @@ fn has_disk_image_extension<P: AsRef<Path>>(file: P) -> Result<()> {
 #[command(color = clap::ColorChoice::Auto)]
 #[command(styles=styles())]
 struct Cli {
-    /// IP address or hostname of ultimate device
+    /// IP address or hostname of ultimate device; separate several with commas
     #[clap(env = "ULTIMATE_HOST")]
-    #[arg(value_parser = Host::parse)]
-    host: Host,
+    #[arg(value_parser = auxiliary::parse_host, value_delimiter = ',', num_args = 1, action = clap::ArgAction::Set)]
+    hosts: Vec<Host>,
+    /// Named device profile or group from the settings file; separate several with commas
+    /// [default: profile set as `default`]
+    #[clap(env = "ULTIMATE_DEVICE")]
+    #[clap(long, short = 'd', value_delimiter = ',')]
+    pub device: Vec<String>,
     /// Subcommand to run
     #[command(subcommand)]
     command: Commands,
@@ -55,12 +119,92 @@ struct Cli {
     #[clap(env = "ULTIMATE_PASSWORD")]
     #[clap(long, short = 'p')]
     pub password: Option<String>,
+    /// Run against the built-in 6502 emulator instead of a device
+    #[clap(long, action)]
+    pub offline: bool,
+    /// TCP port of the REST API [default: 80]
+    #[clap(long)]
+    pub port: Option<u16>,
+    /// Seconds to wait for a connection to the device
+    #[clap(long, default_value_t = 5.0)]
+    pub connect_timeout: f64,
+    /// Seconds to wait for a request to complete
+    #[clap(long, default_value_t = 30.0)]
+    pub timeout: f64,
+    /// Retries with increasing delay for reads and state-setting requests failing with a network error;
+    /// actions such as reset or run are never repeated
+    #[clap(long, default_value_t = 0)]
+    pub retries: u32,
+    /// Parallel connections for memory transfers larger than 4 KiB
+    #[clap(long, default_value_t = 1)]
+    pub connections: usize,
 }
 
-#[derive(Debug, Subcommand)]
+#[derive(Debug, Clone, Subcommand)]
 enum Commands {
+    /// Show or change device configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Read, write, or wait for the U64 debug register ($D7FF)
+    Debugreg {
+        /// Value to write; otherwise read and print
+        #[arg(value_parser = parse::<u8>, conflicts_with = "wait_for")]
+        value: Option<u8>,
+        /// Wait until the register holds this value; exits with an error on timeout
+        #[clap(long)]
+        #[arg(value_parser = parse::<u8>)]
+        wait_for: Option<u8>,
+        /// Timeout in seconds when waiting
+        #[clap(long, default_value_t = 10.0, requires = "wait_for")]
+        timeout: f64,
+    },
+    /// Scan the local network for Ultimate devices
+    Discover {
+        /// IPv4 subnet to scan, e.g. 192.168.1.0/24 [default: subnets of all network interfaces]
+        subnet: Option<Subnet>,
+        /// Only print the address of the device with this unique ID
+        #[clap(long)]
+        id: Option<String>,
+        /// Seconds to wait for each address to answer
+        #[clap(long, default_value_t = 0.5)]
+        timeout: f64,
+    },
+    /// Create disk images on the Ultimate's storage
+    Disk {
+        #[command(subcommand)]
+        command: DiskCommands,
+    },
+    /// Control emulated disk drive
+    Drive {
+        /// Drive to control [default: from device profile, or a]
+        #[arg(value_enum)]
+        drive: Option<DriveId>,
+        #[command(subcommand)]
+        command: DriveCommands,
+    },
     /// Show drive information
     Drives,
+    /// Search memory for bytes or text and show hits with a hexdump
+    Find {
+        /// Hex bytes with `??` wildcards and ranges, e.g. `a9 ?? 8d 20-21 d0`, or text with `-t`/`-s`
+        pattern: String,
+        /// Search for text encoded as PETSCII
+        #[clap(long, short = 't', conflicts_with = "screen")]
+        text: bool,
+        /// Search for text encoded as screen codes
+        #[clap(long, short = 's')]
+        screen: bool,
+        /// Address range to search as `START-END` or `START:LENGTH`; may be repeated
+        #[clap(long, short = 'r')]
+        #[arg(value_parser = search::parse_range)]
+        range: Vec<RangeInclusive<u16>>,
+        /// Bytes of context shown before and after each hit
+        #[clap(long, short = 'C', default_value = "16")]
+        #[arg(value_parser = parse::<u16>)]
+        context: u16,
+    },
     /// Show Ultimate device information
     Info,
     /// Load file into memory
@@ -82,11 +226,12 @@ enum Commands {
     Menu,
     /// Mount disk image
     Mount {
-        /// Image file
-        file: PathBuf,
-        /// Drive number
-        #[clap(long, short = 'd', default_value = "a")]
-        drive: String,
+        /// Image file; use `device:/Usb0/...` for files on the Ultimate
+        #[arg(value_parser = parse_file_location)]
+        file: FileLocation,
+        /// Drive number [default: from device profile, or a]
+        #[clap(long, short = 'd')]
+        drive: Option<String>,
         /// Mount mode
         #[clap(long, short = 'm', default_value = "ro")]
         #[arg(value_enum)]
@@ -116,8 +261,9 @@ enum Commands {
     },
     /// Play SID or Amiga MOD file
     Play {
-        /// SID or MOD file
-        file: PathBuf,
+        /// SID or MOD file; use `device:/Usb0/...` for files on the Ultimate
+        #[arg(value_parser = parse_file_location)]
+        file: FileLocation,
         /// Optional song number for SID
         #[clap(short = 'n')]
         #[arg(value_parser = parse::<u8>)]
@@ -148,30 +294,50 @@ enum Commands {
     /// Power off machine
     Poweroff,
     /// Reboot machine
-    Reboot,
+    Reboot {
+        /// Wait until the device is back online and BASIC is ready
+        #[clap(long, short = 'w')]
+        wait: bool,
+        /// Timeout in seconds when waiting
+        #[clap(long, default_value_t = 60.0, requires = "wait")]
+        timeout: f64,
+    },
     /// Reset machine
-    Reset,
+    Reset {
+        /// Wait until BASIC is ready
+        #[clap(long, short = 'w')]
+        wait: bool,
+        /// Timeout in seconds when waiting
+        #[clap(long, default_value_t = 30.0, requires = "wait")]
+        timeout: f64,
+    },
     /// Resume machine
     Resume,
     /// Load and run PRG or CRT file
     #[command(arg_required_else_help = true)]
     Run {
-        /// PRG or CRT file to load and run
-        file: PathBuf,
+        /// PRG or CRT file to load and run; use `device:/Usb0/...` for files on the Ultimate
+        #[arg(value_parser = parse_file_location)]
+        file: FileLocation,
     },
     /// Take C64 screenshot via VIC stream
     Screenshot {
         /// Optionally output to file (png, jpg), otherwise attempt to print on console
         #[clap(long, short = 'o', default_value = None)]
         output: Option<PathBuf>,
-        /// URL to streaming Ultimate device
-        #[clap(long, default_value = "udp://239.0.1.64:11000")]
+        /// URL to streaming Ultimate device [default: udp://239.0.1.64:11000, or profile stream address]
+        #[clap(long)]
         #[arg(value_parser = Url::parse)]
-        url: Url,
+        url: Option<Url>,
         /// Scale image when outputting to file
         #[clap(long, short = 'x', default_value_t = 1)]
         scale: u32,
     },
+    /// Save or restore RAM and I/O chip registers
+    Snapshot {
+        #[command(subcommand)]
+        command: SnapshotCommands,
+    },
     /// Start or stop video, audio, or debug stream
     #[command(group(
     clap::ArgGroup::new("action")
@@ -179,10 +345,10 @@ enum Commands {
         .required(true) // must give one
 ))]
     Stream {
-        /// Destination address for stream
-        #[clap(long, default_value = "239.0.1.64")]
+        /// Destination address for stream [default: 239.0.1.64, or profile stream address]
+        #[clap(long)]
         #[arg(value_parser = Host::parse)]
-        ip: Host,
+        ip: Option<Host>,
         /// Port number [default: 11000 (video), 11001 (audio), 11002 (debug)]
         #[clap(long, short = 'p')]
         port: Option<u16>,
@@ -201,6 +367,215 @@ enum Commands {
         /// Unicode text to type - will be converted to PETSCII
         text: String,
     },
+    /// Wait until the device answers, e.g. after power-on
+    Wait {
+        /// Also wait for the BASIC READY prompt
+        #[clap(long, short = 'r')]
+        ready: bool,
+        /// Timeout in seconds; exits with an error when exceeded
+        #[clap(long, default_value_t = 60.0)]
+        timeout: f64,
+    },
+    /// Poll memory and print bytes as they change
+    Watch {
+        /// Regions as `[NAME=]ADDRESS[:LENGTH]`, e.g. `0xd020` or `border=53280:2`
+        #[clap(required = true)]
+        regions: Vec<String>,
+        /// Number of bytes for regions without a length
+        #[clap(long, short = 'n', default_value = "1")]
+        #[arg(value_parser = parse::<u16>)]
+        length: u16,
+        /// Time between polls, e.g. `50ms` or `1s`
+        #[clap(long, short = 'i', default_value = "100ms")]
+        #[arg(value_parser = auxiliary::parse_duration)]
+        interval: Duration,
+        /// Stop after this time instead of running until interrupted
+        #[clap(long)]
+        #[arg(value_parser = auxiliary::parse_duration)]
+        duration: Option<Duration>,
+        /// Also log initial values and changes to CSV file
+        #[clap(long)]
+        csv: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand)]
+enum ConfigCommands {
+    /// List categories, or items in a category
+    List {
+        /// Category name, e.g. "Audio Mixer"
+        category: Option<String>,
+    },
+    /// Show configuration item with allowed values
+    Get {
+        /// Category name, e.g. "Audio Mixer"
+        category: String,
+        /// Item name, e.g. "Vol UltiSid 1"
+        item: String,
+    },
+    /// Set one or more items in a category
+    Set {
+        /// Category name, e.g. "Audio Mixer"
+        category: String,
+        /// Assignments, e.g. "Vol UltiSid 1=0 dB"
+        #[arg(value_parser = parse_assignment, required = true)]
+        items: Vec<(String, ConfigValue)>,
+    },
+    /// Export all categories and items as a profile
+    Export {
+        /// Output file; otherwise print to stdout
+        #[clap(long, short = 'o')]
+        output: Option<PathBuf>,
+        /// Profile format [default: deduced from output file, otherwise toml]
+        #[clap(long, short = 'f')]
+        #[arg(value_enum)]
+        format: Option<ProfileFormat>,
+    },
+    /// Import profile, only changing items that differ
+    Import {
+        /// TOML or JSON profile file
+        file: PathBuf,
+        /// Only show items that would change
+        #[clap(long, short = 'n', action)]
+        dry_run: bool,
+        /// Save configuration to flash after importing
+        #[clap(long, action, conflicts_with = "dry_run")]
+        save: bool,
+    },
+    /// Compare configuration with another device or a profile file
+    Diff {
+        /// Profile file (toml, json) or IP address/hostname of other device
+        other: String,
+        /// Print differences as JSON
+        #[clap(long, action)]
+        json: bool,
+    },
+    /// Save current configuration to flash
+    Save,
+    /// Load configuration from flash, discarding unsaved changes
+    Load,
+    /// Reset configuration to factory defaults
+    Reset,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+enum DiskCommands {
+    /// Create blank d64, d71, d81, or dnp image
+    New {
+        /// Path on the Ultimate, e.g. `/Usb0/save.d64`; type is deduced from extension
+        path: String,
+        /// Disk name
+        #[clap(long, short = 'n')]
+        name: Option<String>,
+        /// Number of tracks (d64: 35 or 40; dnp: 1-255)
+        #[clap(long, short = 't')]
+        tracks: Option<u8>,
+        /// Mount the new image read/write in this drive
+        #[clap(long, short = 'm')]
+        #[arg(value_enum)]
+        mount: Option<DriveId>,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand)]
+enum SnapshotCommands {
+    /// Pause, save RAM and I/O chip registers, and resume
+    Save {
+        #[command(flatten)]
+        location: SnapshotLocation,
+        /// Write VICE snapshot (.vsf) instead
+        #[clap(long, action)]
+        vsf: bool,
+    },
+    /// Pause, restore a saved snapshot or VICE snapshot (.vsf), and resume
+    Load {
+        #[command(flatten)]
+        location: SnapshotLocation,
+    },
+    /// List named snapshot slots
+    List,
+}
+
+/// Snapshot file or named slot
+#[derive(Debug, Clone, Args)]
+#[group(required = true, multiple = false)]
+struct SnapshotLocation {
+    /// Snapshot file
+    file: Option<PathBuf>,
+    /// Named slot in the ru64 configuration directory
+    #[clap(long, short = 's')]
+    slot: Option<String>,
+}
+
+impl SnapshotLocation {
+    fn path(&self) -> Result<PathBuf> {
+        match (&self.file, &self.slot) {
+            (Some(file), _) => Ok(file.clone()),
+            (None, Some(slot)) => Ok(snapshot::slot_path(slot)?),
+            (None, None) => bail!("no snapshot file or slot given"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Subcommand)]
+enum DriveCommands {
+    /// Turn drive on
+    On,
+    /// Turn drive off
+    Off,
+    /// Reset drive
+    Reset,
+    /// Eject disk image
+    Eject,
+    /// Unlink disk image so that changes are not written back
+    Unlink,
+    /// Upload custom drive ROM, e.g. JiffyDOS
+    Rom {
+        /// ROM image file (16K or 32K)
+        file: PathBuf,
+    },
+    /// Switch drive type
+    Mode {
+        /// Drive type
+        #[arg(value_enum)]
+        mode: DriveType,
+    },
+}
+
+/// Device feature needed by a command, checked before running it
+fn required_feature(command: &Commands) -> Option<Feature> {
+    match command {
+        Commands::Debugreg { .. } => Some(Feature::DebugRegister),
+        Commands::Disk { .. } => Some(Feature::CreateDiskImage),
+        Commands::Drive {
+            command: DriveCommands::Mode { .. },
+            ..
+        } => Some(Feature::DriveMode),
+        Commands::Drive {
+            command: DriveCommands::Rom { .. },
+            ..
+        } => Some(Feature::DriveRom),
+        Commands::Poweroff => Some(Feature::Poweroff),
+        Commands::Stream { .. } => Some(Feature::Streams),
+        _ => None,
+    }
+}
+
+/// Progress callback for memory transfers; prints to stderr if it is a terminal and
+/// the transfer takes more than one request
+fn print_progress(action: &'static str) -> impl FnMut(usize, usize) {
+    let terminal = std::io::stderr().is_terminal();
+    let mut printed = false;
+    move |done, total| {
+        if !terminal || (done == total && !printed) {
+            return;
+        }
+        eprint!("\r{action} {done}/{total} bytes");
+        if done == total {
+            eprintln!();
+        }
+        printed = true;
+    }
 }
 
 /// Disassemble `length` bytes from memory, starting at `address`
@@ -216,10 +591,111 @@ fn print_disassembled(bytes: &[u8], address: u16) -> Result<()> {
     Ok(())
 }
 
+/// Device to run a command on, with defaults from its profile
+struct Target {
+    /// Profile name or host, used in the summary of group runs
+    name: String,
+    host: Option<Host>,
+    password: Option<String>,
+    port: u16,
+    drive: DriveId,
+    stream: Ipv4Addr,
+}
+
+impl Target {
+    /// Command line arguments take precedence over the profile
+    fn new(name: &str, host: Option<Host>, args: &Cli, profile: &DeviceProfile) -> Self {
+        Self {
+            name: name.to_string(),
+            host,
+            password: args.password.clone().or_else(|| profile.password.clone()),
+            port: args.port.or(profile.port).unwrap_or(80),
+            drive: profile.drive.unwrap_or(DriveId::A),
+            stream: profile.stream.unwrap_or(DEFAULT_STREAM_ADDRESS),
+        }
+    }
+
+    /// Connect to `host` with port and password of this target
+    fn connect(&self, args: &Cli, host: &Host) -> Result<Rest> {
+        let rest = RestBuilder::new(host)
+            .port(self.port)
+            .password(self.password.clone())
+            .connect_timeout(Duration::from_secs_f64(args.connect_timeout))
+            .timeout(Duration::from_secs_f64(args.timeout))
+            .retries(args.retries)
+            .connections(args.connections)
+            .build()?;
+        Ok(rest)
+    }
+}
+
+/// Devices selected by hosts and device profiles; a single target without host if none are given
+///
+/// Each device is selected once, so target names are unique.
+fn targets(args: &Cli, settings: &Settings) -> Result<Vec<Target>> {
+    let profiles = settings.select(&args.device)?;
+    if !args.hosts.is_empty() {
+        ensure!(
+            profiles.len() <= 1,
+            "hosts cannot be combined with a group of device profiles"
+        );
+        let profile = profiles
+            .first()
+            .map(|(_, profile)| (*profile).clone())
+            .unwrap_or_default();
+        let mut hosts: Vec<&Host> = Vec::new();
+        for host in &args.hosts {
+            if !hosts.contains(&host) {
+                hosts.push(host);
+            }
+        }
+        return Ok(hosts
+            .into_iter()
+            .map(|host| Target::new(&host.to_string(), Some(host.clone()), args, &profile))
+            .collect());
+    }
+    if profiles.is_empty() {
+        return Ok(vec![Target::new("", None, args, &DeviceProfile::default())]);
+    }
+    profiles
+        .into_iter()
+        .map(|(name, profile)| Ok(Target::new(name, profile.host()?, args, profile)))
+        .collect()
+}
+
+/// Whether the command can run on several devices at once; commands printing device output cannot
+fn runs_on_group(command: &Commands) -> bool {
+    !matches!(
+        command,
+        Commands::Config {
+            command: ConfigCommands::List { .. }
+                | ConfigCommands::Get { .. }
+                | ConfigCommands::Export { .. }
+                | ConfigCommands::Import { .. }
+                | ConfigCommands::Diff { .. }
+        } | Commands::Debugreg {
+            value: None,
+            wait_for: None,
+            ..
+        } | Commands::Discover { .. }
+            | Commands::Drive {
+                command: DriveCommands::Rom { .. },
+                ..
+            }
+            | Commands::Drives
+            | Commands::Find { .. }
+            | Commands::Info
+            | Commands::Peek { .. }
+            | Commands::Screenshot { .. }
+            | Commands::Snapshot {
+                command: SnapshotCommands::Save { .. } | SnapshotCommands::List
+            }
+            | Commands::Watch { .. }
+    )
+}
+
 fn do_main() -> Result<()> {
     let args = Cli::parse();
-    let ultimate = Rest::new(&args.host, args.password.clone())?;
-
     if args.verbose && std::env::var(DEFAULT_FILTER_ENV).is_err() {
         std::env::set_var(DEFAULT_FILTER_ENV, "Debug");
     } else {
@@ -227,7 +703,243 @@ fn do_main() -> Result<()> {
     }
     pretty_env_logger::init();
 
-    match args.command {
+    let settings = Settings::load().context("failed to load device profiles")?;
+    let targets = targets(&args, &settings)?;
+    if let [target] = targets.as_slice() {
+        ensure!(
+            target.host.is_some()
+                || args.offline
+                || matches!(
+                    args.command,
+                    Commands::Discover { .. }
+                        | Commands::Snapshot {
+                            command: SnapshotCommands::List
+                        }
+                ),
+            "no host given; pass it as argument, set ULTIMATE_HOST, select a device profile, or use --offline"
+        );
+        let rest = match (&target.host, args.offline) {
+            (Some(host), false) => Some(target.connect(&args, host)?),
+            _ => None,
+        };
+        return run_command(&args, target, rest.as_ref());
+    }
+
+    ensure!(
+        !args.offline,
+        "--offline cannot be used with several devices"
+    );
+    ensure!(
+        runs_on_group(&args.command),
+        "command prints device output and cannot run on several devices at once"
+    );
+    let group = targets
+        .iter()
+        .map(|target| {
+            let host = target
+                .host
+                .as_ref()
+                .with_context(|| format!("no host in device profile {}", target.name))?;
+            Ok((target.name.clone(), target.connect(&args, host)?))
+        })
+        .collect::<Result<RestGroup>>()?;
+    let results = group.fan_out(|name, rest| {
+        let target = targets
+            .iter()
+            .find(|target| target.name == name)
+            .with_context(|| format!("no device profile for group member {name}"))?;
+        run_command(&args, target, Some(rest))
+    });
+    print_group_table(&results);
+    let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+    ensure!(failed == 0, "{failed} of {} devices failed", results.len());
+    Ok(())
+}
+
+/// Run command on a device, or on the emulator if `rest` is `None`
+fn run_command(args: &Cli, target: &Target, rest: Option<&Rest>) -> Result<()> {
+    if let (Some(rest), Some(feature)) = (rest, required_feature(&args.command)) {
+        rest.require(feature)?;
+    }
+    let emulator;
+    let ultimate: &dyn Ultimate = match rest {
+        Some(rest) => rest,
+        None => {
+            emulator = Emulator::default();
+            &emulator
+        }
+    };
+    let device = || rest.context("command not available in offline mode");
+
+    match args.command.clone() {
+        Commands::Config { command } => match command {
+            ConfigCommands::List { category: None } => {
+                device()?
+                    .config_categories()?
+                    .iter()
+                    .for_each(|category| println!("{category}"));
+            }
+            ConfigCommands::List {
+                category: Some(category),
+            } => {
+                let items = device()?.config_category(&category)?;
+                print_config_table(&items);
+            }
+            ConfigCommands::Get { category, item } => {
+                let config_item = device()?.config_item(&category, &item)?;
+                print_config_item(&item, &config_item);
+            }
+            ConfigCommands::Set { category, items } => {
+                if let [(item, value)] = items.as_slice() {
+                    device()?.set_config_item(&category, item, value)?;
+                } else {
+                    // sent as JSON, so numeric items need integer values
+                    let current = device()?.config_category(&category)?;
+                    let items = items
+                        .into_iter()
+                        .map(|(item, value)| {
+                            let value = match current.get(&item) {
+                                Some(current) => value.with_type_of(current),
+                                None => value,
+                            };
+                            (item, value)
+                        })
+                        .collect();
+                    let config = Configuration::from([(category, items)]);
+                    device()?.set_config_items(&config)?;
+                }
+            }
+            ConfigCommands::Export { output, format } => {
+                let format = format.unwrap_or_else(|| {
+                    output
+                        .as_ref()
+                        .map(ProfileFormat::from_file_name)
+                        .unwrap_or_default()
+                });
+                let profile = format.serialize(&device()?.configuration()?)?;
+                match output {
+                    Some(path) => fs::write(path, profile)?,
+                    None => print!("{profile}"),
+                }
+            }
+            ConfigCommands::Import {
+                file,
+                dry_run,
+                save,
+            } => {
+                let profile = config::read_profile(&file)?;
+                let current = device()?.configuration()?;
+                let changes = config::changed_items(&current, &profile);
+                if changes.is_empty() {
+                    println!("No changes");
+                } else {
+                    print_config_changes(&current, &changes);
+                    if !dry_run {
+                        device()?.set_config_items(&changes)?;
+                    }
+                }
+                if save {
+                    device()?.save_config_to_flash()?;
+                }
+            }
+            ConfigCommands::Diff { other, json } => {
+                let other_config = if Path::new(&other).is_file() {
+                    config::read_profile(&other)?
+                } else {
+                    target
+                        .connect(args, &auxiliary::parse_host(&other)?)?
+                        .configuration()?
+                };
+                let differences = config::diff(&device()?.configuration()?, &other_config);
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&differences)?);
+                } else if differences.is_empty() {
+                    println!("No differences");
+                } else {
+                    print_config_diff(
+                        &target
+                            .host
+                            .as_ref()
+                            .map(|h| h.to_string())
+                            .unwrap_or_default(),
+                        &other,
+                        &differences,
+                    );
+                }
+            }
+            ConfigCommands::Save => device()?.save_config_to_flash()?,
+            ConfigCommands::Load => device()?.load_config_from_flash()?,
+            ConfigCommands::Reset => device()?.reset_config_to_defaults()?,
+        },
+        Commands::Debugreg {
+            value,
+            wait_for,
+            timeout,
+        } => {
+            if let Some(value) = value {
+                ultimate.write_debugreg(value)?;
+            } else if let Some(expected) = wait_for {
+                ultimate.wait_for_debugreg(expected, Duration::from_secs_f64(timeout))?;
+            } else {
+                println!("{:#04x}", ultimate.read_debugreg()?);
+            }
+        }
+        Commands::Discover {
+            subnet,
+            id,
+            timeout,
+        } => {
+            let subnets = match subnet {
+                Some(subnet) => vec![subnet],
+                None => discovery::local_subnets()?,
+            };
+            let scan = Scan {
+                port: target.port,
+                password: target.password.clone(),
+                timeout: Duration::from_secs_f64(timeout),
+                ..Scan::new(subnets)
+            };
+            match id {
+                Some(id) => println!("{}", scan.resolve(&id)?),
+                None => print_device_table(scan.run()?),
+            }
+        }
+        Commands::Disk { command } => match command {
+            DiskCommands::New {
+                path,
+                name,
+                tracks,
+                mount,
+            } => {
+                let path = path.strip_prefix(DEVICE_PREFIX).unwrap_or(&path);
+                let kind = drives::DiskImageType::from_file_name(path)?;
+                device()?.create_disk_image(path, kind, name.as_deref(), tracks)?;
+                if let Some(drive) = mount {
+                    device()?.mount_remote_image(
+                        path,
+                        drive.to_string(),
+                        drives::MountMode::ReadWrite,
+                        false,
+                    )?;
+                }
+            }
+        },
+        Commands::Drive { drive, command } => {
+            let drive = drive.unwrap_or(target.drive);
+            match command {
+                DriveCommands::On => device()?.drive_on(drive)?,
+                DriveCommands::Off => device()?.drive_off(drive)?,
+                DriveCommands::Reset => device()?.drive_reset(drive)?,
+                DriveCommands::Eject => device()?.drive_eject(drive)?,
+                DriveCommands::Unlink => device()?.drive_unlink(drive)?,
+                DriveCommands::Rom { file } => {
+                    let data = fs::read(&file)?;
+                    let status = device()?.load_drive_rom(drive, &data)?;
+                    println!("ROM: {}", status.rom.as_deref().unwrap_or("N/A"));
+                }
+                DriveCommands::Mode { mode } => device()?.drive_set_mode(drive, mode)?,
+            }
+        }
         Commands::Drives => {
             let drives = ultimate.drive_list()?;
             print_drive_table(drives);
@@ -248,14 +960,11 @@ fn do_main() -> Result<()> {
                 ultimate.reset()?;
             }
 
-            let (address, _) = ultimate.load_data(&data, address)?;
+            let (address, _) =
+                ultimate.load_data_with_progress(&data, address, &mut print_progress("Loaded"))?;
 
             if run {
-                if address == BASIC_LOAD_ADDR {
-                    ultimate.type_text("run\n")?;
-                } else {
-                    ultimate.type_text(&format!("sys{address}\n"))?
-                }
+                ultimate.run_loaded(address)?;
             }
         }
         Commands::Menu => {
@@ -267,8 +976,52 @@ fn do_main() -> Result<()> {
             mode,
             run,
         } => {
-            has_disk_image_extension(&file)?;
-            ultimate.mount_disk_image(&file, drive_id, mode, run)?;
+            let drive_id = drive_id.unwrap_or_else(|| target.drive.to_string());
+            has_disk_image_extension(file.path())?;
+            match file {
+                FileLocation::Local(path) => {
+                    ultimate.mount_disk_image(&path, drive_id, mode, run)?
+                }
+                FileLocation::Device(path) => {
+                    device()?.mount_remote_image(&path, drive_id, mode, run)?
+                }
+            }
+        }
+        Commands::Find {
+            pattern,
+            text,
+            screen,
+            range,
+            context,
+        } => {
+            let pattern = if text {
+                Pattern::petscii(&pattern)?
+            } else if screen {
+                Pattern::screen_codes(&pattern)?
+            } else {
+                Pattern::hex(&pattern)?
+            };
+            let memory = Memory::read_with_progress(ultimate, &mut print_progress("Read"))?;
+            let ranges = if range.is_empty() {
+                vec![0..=0xffff]
+            } else {
+                range
+            };
+            let mut hits = 0;
+            for range in ranges {
+                for address in memory.find(&pattern, range) {
+                    let last = address + (pattern.len() - 1) as u16;
+                    println!("{address:#06x}:");
+                    print!(
+                        "{}",
+                        memory.hexdump(
+                            address.saturating_sub(context)..=last.saturating_add(context)
+                        )
+                    );
+                    hits += 1;
+                }
+            }
+            ensure!(hits > 0, "pattern {pattern} not found");
         }
         Commands::Pause => {
             ultimate.pause()?;
@@ -282,7 +1035,8 @@ fn do_main() -> Result<()> {
             outfile,
             disassemble,
         } => {
-            let data = ultimate.read_mem(address, length)?;
+            let data =
+                ultimate.read_mem_with_progress(address, length, &mut print_progress("Read"))?;
             if disassemble {
                 print_disassembled(&data, address)?;
             } else if outfile.is_some() {
@@ -295,11 +1049,14 @@ fn do_main() -> Result<()> {
             }
         }
         Commands::Play { file, songnr } => {
-            let data = fs::read(&file)?;
-            let ext = auxiliary::get_extension(&file).unwrap_or_default();
-            match ext.as_str() {
-                "sid" => ultimate.sid_play(&data, songnr)?,
-                "mod" => ultimate.mod_play(&data)?,
+            let ext = auxiliary::get_extension(file.path()).unwrap_or_default();
+            match (ext.as_str(), file) {
+                ("sid", FileLocation::Local(path)) => {
+                    ultimate.sid_play(&fs::read(path)?, songnr)?
+                }
+                ("sid", FileLocation::Device(path)) => device()?.sid_play_remote(&path, songnr)?,
+                ("mod", FileLocation::Local(path)) => ultimate.mod_play(&fs::read(path)?)?,
+                ("mod", FileLocation::Device(path)) => device()?.mod_play_remote(&path)?,
                 _ => bail!("Unsupported music file format: {ext}"),
             }
         }
@@ -335,25 +1092,62 @@ fn do_main() -> Result<()> {
             debug!("Poke {value:#04x} to {address:#06x}");
             ultimate.write_mem(address, &[value])?;
         }
-        Commands::Reboot => {
+        Commands::Reboot { wait, timeout } => {
             ultimate.reboot()?;
+            if wait {
+                std::thread::sleep(SETTLE_DELAY);
+                device()?.wait_ready(Duration::from_secs_f64(timeout))?;
+            }
         }
-        Commands::Reset => {
+        Commands::Reset { wait, timeout } => {
             ultimate.reset()?;
+            if wait {
+                std::thread::sleep(SETTLE_DELAY);
+                device()?.wait_ready(Duration::from_secs_f64(timeout))?;
+            }
         }
         Commands::Resume => {
             ultimate.resume()?;
         }
         Commands::Run { file } => {
-            let data = fs::read(&file)?;
-            match auxiliary::get_extension(&file).unwrap_or_default().as_str() {
-                "crt" => ultimate.run_crt(&data)?,
-                _ => ultimate.run_prg(&data)?,
+            let is_crt = auxiliary::get_extension(file.path()).as_deref() == Some("crt");
+            match (is_crt, file) {
+                (true, FileLocation::Local(path)) => ultimate.run_crt(&fs::read(path)?)?,
+                (true, FileLocation::Device(path)) => device()?.run_remote_crt(&path)?,
+                (false, FileLocation::Local(path)) => ultimate.run_prg(&fs::read(path)?)?,
+                (false, FileLocation::Device(path)) => device()?.run_remote_prg(&path)?,
             }
         }
         Commands::Screenshot { output, url, scale } => {
+            let url = match url {
+                Some(url) => url,
+                None => Url::parse(&format!("udp://{}:11000", target.stream))?,
+            };
             vicstream::take_snapshot(&url, output.as_deref(), Some(scale))?;
         }
+        Commands::Snapshot { command } => match command {
+            SnapshotCommands::Save { location, vsf } => {
+                let path = location.path()?;
+                if let Some(dir) = path.parent().filter(|_| location.slot.is_some()) {
+                    fs::create_dir_all(dir)?;
+                }
+                let snapshot = MachineSnapshot::capture(ultimate)?;
+                if vsf {
+                    snapshot.save_vsf(&path)?;
+                } else {
+                    snapshot.save(&path)?;
+                }
+                debug!("Saved snapshot to {}", path.display());
+            }
+            SnapshotCommands::Load { location } => {
+                MachineSnapshot::load(location.path()?)?.restore(ultimate)?;
+            }
+            SnapshotCommands::List => {
+                snapshot::slots()?
+                    .iter()
+                    .for_each(|slot| println!("{slot}"));
+            }
+        },
         Commands::Stream {
             ip,
             port,
@@ -362,19 +1156,141 @@ fn do_main() -> Result<()> {
             stop,
         } => {
             let port = port.unwrap_or_else(|| kind.default_port());
+            let ip = ip.unwrap_or(Host::Ipv4(target.stream));
             if start {
-                ultimate.start_stream(&ip, port, kind)?;
+                device()?.start_stream(&ip, port, kind)?;
             } else if stop {
-                ultimate.stop_stream(kind)?;
+                device()?.stop_stream(kind)?;
             }
         }
         Commands::Type { text } => {
             ultimate.type_text(&text)?;
         }
+        Commands::Wait { ready, timeout } => {
+            let timeout = Duration::from_secs_f64(timeout);
+            if ready {
+                device()?.wait_ready(timeout)?;
+            } else {
+                device()?.wait_online(timeout)?;
+            }
+        }
+        Commands::Watch {
+            regions,
+            length,
+            interval,
+            duration,
+            csv,
+        } => {
+            let regions = regions
+                .iter()
+                .map(|region| Region::parse(region, length))
+                .collect::<ultimate64::Result<Vec<_>>>()?;
+            watch_memory(ultimate, regions, interval, duration, csv.as_deref())?;
+        }
     }
     Ok(())
 }
 
+/// Poll `regions` every `interval`, printing changed bytes and optionally logging to CSV
+fn watch_memory(
+    ultimate: &dyn Ultimate,
+    regions: Vec<Region>,
+    interval: Duration,
+    duration: Option<Duration>,
+    csv: Option<&Path>,
+) -> Result<()> {
+    let mut log = csv
+        .map(|path| -> Result<_> {
+            let mut file = BufWriter::new(
+                fs::File::create(path)
+                    .with_context(|| format!("cannot create {}", path.display()))?,
+            );
+            writeln!(file, "time,address,name,old,new")?;
+            Ok(file)
+        })
+        .transpose()?;
+    let mut watcher = Watcher::new(regions)?;
+    let mut initial = true;
+    loop {
+        let changes = watcher.poll(ultimate)?;
+        for change in &changes {
+            if !initial {
+                println!("{change}");
+            }
+            if let Some(log) = log.as_mut() {
+                writeln!(
+                    log,
+                    "{:.3},{:#06x},{},{},{}",
+                    change.elapsed.as_secs_f64(),
+                    change.address,
+                    csv_field(change.label.as_deref().unwrap_or_default()),
+                    change.old.map(|old| old.to_string()).unwrap_or_default(),
+                    change.new
+                )?;
+            }
+        }
+        if let Some(log) = log.as_mut() {
+            log.flush()?;
+        }
+        initial = false;
+        if duration.is_some_and(|duration| watcher.elapsed() >= duration) {
+            return Ok(());
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+/// Quote a CSV field if it contains separators, quotes or line breaks
+fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+fn print_group_table(results: &[(&str, Result<()>)]) {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+
+    table.set_titles(Row::new(vec![Cell::new("Device"), Cell::new("Result")]));
+
+    for (name, result) in results {
+        let outcome = match result {
+            Ok(()) => "ok".to_string(),
+            Err(err) => format!("failed: {err}"),
+        };
+        table.add_row(Row::new(vec![Cell::new(name), Cell::new(&outcome)]));
+    }
+
+    table.printstd();
+}
+
+fn print_device_table(devices: Vec<(IpAddr, DeviceInfo)>) {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+
+    table.set_titles(Row::new(vec![
+        Cell::new("Address"),
+        Cell::new("Product"),
+        Cell::new("Hostname"),
+        Cell::new("Firmware"),
+        Cell::new("Unique ID"),
+    ]));
+
+    for (ip, info) in devices {
+        table.add_row(Row::new(vec![
+            Cell::new(&ip.to_string()),
+            Cell::new(&info.product),
+            Cell::new(&info.hostname),
+            Cell::new(&info.firmware_version),
+            Cell::new(info.unique_id.as_deref().unwrap_or("")),
+        ]));
+    }
+
+    table.printstd();
+}
+
 fn print_drive_table(drives: HashMap<String, Drive>) {
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
@@ -404,6 +1320,92 @@ fn print_drive_table(drives: HashMap<String, Drive>) {
     table.printstd();
 }
 
+fn print_config_table(items: &ConfigCategory) {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(Row::new(vec![Cell::new("Item"), Cell::new("Value")]));
+    for (name, value) in items {
+        table.add_row(Row::new(vec![
+            Cell::new(name),
+            Cell::new(&value.to_string()),
+        ]));
+    }
+    table.printstd();
+}
+
+fn print_config_item(name: &str, item: &ConfigItem) {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(Row::new(vec![
+        Cell::new("Item"),
+        Cell::new("Current"),
+        Cell::new("Default"),
+        Cell::new("Allowed"),
+    ]));
+    table.add_row(Row::new(vec![
+        Cell::new(name),
+        Cell::new(&item.current.to_string()),
+        Cell::new(
+            &item
+                .default
+                .as_ref()
+                .map_or(String::new(), |d| d.to_string()),
+        ),
+        Cell::new(&item.allowed()),
+    ]));
+    table.printstd();
+}
+
+fn print_config_changes(current: &Configuration, changes: &Configuration) {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(Row::new(vec![
+        Cell::new("Category"),
+        Cell::new("Item"),
+        Cell::new("Current"),
+        Cell::new("New"),
+    ]));
+    for (category, items) in changes {
+        for (item, value) in items {
+            let old = current
+                .get(category)
+                .and_then(|c| c.get(item))
+                .map_or("N/A".to_string(), |v| v.to_string());
+            table.add_row(Row::new(vec![
+                Cell::new(category),
+                Cell::new(item),
+                Cell::new(&old),
+                Cell::new(&value.to_string()),
+            ]));
+        }
+    }
+    table.printstd();
+}
+
+/// Print one table per category with differing items
+fn print_config_diff(left_name: &str, right_name: &str, differences: &[ConfigDifference]) {
+    let value_or_na =
+        |v: &Option<ConfigValue>| v.as_ref().map_or("N/A".to_string(), |v| v.to_string());
+    for group in differences.chunk_by(|a, b| a.category == b.category) {
+        println!("{}", group[0].category);
+        let mut table = Table::new();
+        table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+        table.set_titles(Row::new(vec![
+            Cell::new("Item"),
+            Cell::new(left_name),
+            Cell::new(right_name),
+        ]));
+        for d in group {
+            table.add_row(Row::new(vec![
+                Cell::new(&d.item),
+                Cell::new(&value_or_na(&d.left)),
+                Cell::new(&value_or_na(&d.right)),
+            ]));
+        }
+        table.printstd();
+    }
+}
+
 fn main() {
     if let Err(err) = do_main() {
         eprintln!("Error: {}", &err);
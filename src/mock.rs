@@ -0,0 +1,544 @@
+//! # Mock Ultimate device for tests
+//!
+//! A small local HTTP server implementing the `/v1` endpoints used by this
+//! crate, backed by a 64K RAM model. Lets [`Rest`](crate::Rest) run end to
+//! end without hardware or network access.
+//!
+//! # Examples
+//! ```
+//! use ultimate64::{mock::MockServer, Rest};
+//! let server = MockServer::start(Some("secret")).unwrap();
+//! let ultimate = Rest::with_port(&server.host(), server.port(), Some("secret".into())).unwrap();
+//! ultimate.write_mem(0x1000, &[1, 2, 3]).unwrap();
+//! assert_eq!(ultimate.read_mem(0x1000, 3).unwrap(), [1, 2, 3]);
+//! assert_eq!(server.state().ram[0x1000], 1);
+//! ```
+
+use crate::{
+    auxiliary::extract_load_address,
+    config::{ConfigValue, Configuration},
+    drives::{Drive, DriveType},
+    DeviceInfo,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, BufRead, BufReader, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+};
+use url::Host;
+
+/// Size of the C64 address space
+const RAM_SIZE: usize = 0x10000;
+
+/// State of the mock device, open for inspection and manipulation by tests
+#[derive(Debug, Clone)]
+pub struct MockState {
+    /// 64K RAM
+    pub ram: Vec<u8>,
+    /// Device information returned by `/v1/info`
+    pub info: DeviceInfo,
+    /// Drives by name
+    pub drives: BTreeMap<String, Drive>,
+    /// U64 debug register ($D7FF)
+    pub debugreg: u8,
+    /// Whether the machine is paused
+    pub paused: bool,
+    /// Configuration categories and items
+    pub config: Configuration,
+    /// Configuration as last saved to flash
+    pub flash: Configuration,
+    /// Received requests as `METHOD path`, e.g. `PUT machine:reset`
+    pub requests: Vec<String>,
+}
+
+impl Default for MockState {
+    fn default() -> Self {
+        let drive = |bus_id| Drive {
+            bus_id,
+            enabled: true,
+            drive_type: Some(DriveType::CBM1541),
+            rom: Some("1541.rom".to_string()),
+            ..Default::default()
+        };
+        let config = Configuration::from([
+            (
+                "Audio Mixer".to_string(),
+                BTreeMap::from([
+                    ("Vol UltiSid 1".to_string(), ConfigValue::from("0 dB")),
+                    ("Vol UltiSid 2".to_string(), ConfigValue::from("0 dB")),
+                ]),
+            ),
+            (
+                "U64 Specific Settings".to_string(),
+                BTreeMap::from([
+                    ("System Mode".to_string(), ConfigValue::from("PAL")),
+                    ("Turbo Control".to_string(), ConfigValue::from("Off")),
+                ]),
+            ),
+        ]);
+        Self {
+            ram: vec![0; RAM_SIZE],
+            info: DeviceInfo {
+                product: "Ultimate 64".to_string(),
+                firmware_version: "3.12".to_string(),
+                fpga_version: "11F".to_string(),
+                core_version: Some("1.45".to_string()),
+                hostname: "mock-u64".to_string(),
+                unique_id: Some("MOCK01".to_string()),
+            },
+            drives: BTreeMap::from([("a".to_string(), drive(8)), ("b".to_string(), drive(9))]),
+            debugreg: 0,
+            paused: false,
+            flash: config.clone(),
+            config,
+            requests: Vec::new(),
+        }
+    }
+}
+
+/// Local HTTP server emulating the Ultimate REST API
+///
+/// The server runs on a background thread for as long as the process lives.
+#[derive(Debug, Clone)]
+pub struct MockServer {
+    /// Local address the server listens on
+    address: SocketAddr,
+    /// Shared device state
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockServer {
+    /// Start server on a free port on localhost
+    ///
+    /// If a password is given, requests without a matching `X-password` header are rejected with 403.
+    pub fn start(password: Option<&str>) -> io::Result<Self> {
+        Self::start_with_state(password, MockState::default())
+    }
+
+    /// Start server with a custom initial state
+    pub fn start_with_state(password: Option<&str>, state: MockState) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let server = Self {
+            address: listener.local_addr()?,
+            state: Arc::new(Mutex::new(state)),
+        };
+        let state = server.state.clone();
+        let password = password.map(str::to_string);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let state = state.clone();
+                let password = password.clone();
+                thread::spawn(move || {
+                    if let Err(e) = handle_connection(stream, &state, password.as_deref()) {
+                        log::debug!("Mock connection error: {e}");
+                    }
+                });
+            }
+        });
+        Ok(server)
+    }
+
+    /// Host to connect to
+    pub fn host(&self) -> Host {
+        Host::Ipv4(Ipv4Addr::LOCALHOST)
+    }
+
+    /// Port to connect to
+    pub fn port(&self) -> u16 {
+        self.address.port()
+    }
+
+    /// Lock and access the device state
+    pub fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Parsed HTTP request
+struct HttpRequest {
+    method: String,
+    /// Percent-decoded path below `/v1/`, e.g. `configs/Audio Mixer`
+    path: String,
+    /// Percent-decoded query parameters
+    query: HashMap<String, String>,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+/// HTTP response status and body
+struct HttpResponse {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl HttpResponse {
+    fn json(status: u16, value: serde_json::Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    fn ok() -> Self {
+        Self::json(200, serde_json::json!({ "errors": [] }))
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self::json(status, serde_json::json!({ "errors": [message] }))
+    }
+
+    fn bytes(body: Vec<u8>) -> Self {
+        Self {
+            status: 200,
+            content_type: "application/octet-stream",
+            body,
+        }
+    }
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    state: &Mutex<MockState>,
+    password: Option<&str>,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let request = read_request(&mut reader)?;
+    let response = if password.is_some()
+        && request.headers.get("x-password").map(String::as_str) != password
+    {
+        HttpResponse::error(403, "Forbidden")
+    } else {
+        let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+        state
+            .requests
+            .push(format!("{} {}", request.method, request.path));
+        route(&request, &mut state)
+    };
+    write_response(stream, &response)
+}
+
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<HttpRequest> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        let trimmed = line.trim_end();
+        if trimmed.is_empty() {
+            break;
+        }
+        if let Some((key, value)) = trimmed.split_once(':') {
+            headers.insert(key.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let body = if headers
+        .get("transfer-encoding")
+        .is_some_and(|v| v.eq_ignore_ascii_case("chunked"))
+    {
+        read_chunked(reader)?
+    } else {
+        let length = headers
+            .get("content-length")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0);
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+        body
+    };
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let path = percent_decode(path.trim_start_matches("/v1/"), false);
+    let query = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(k, v)| (percent_decode(k, true), percent_decode(v, true)))
+        .collect();
+
+    Ok(HttpRequest {
+        method,
+        path,
+        query,
+        headers,
+        body,
+    })
+}
+
+fn read_chunked<R: BufRead>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        let size = usize::from_str_radix(line.trim(), 16)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut chunk = vec![0; size + 2]; // including trailing CRLF
+        reader.read_exact(&mut chunk)?;
+        if size == 0 {
+            return Ok(body);
+        }
+        body.extend_from_slice(&chunk[..size]);
+    }
+}
+
+fn write_response(mut stream: TcpStream, response: &HttpResponse) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {} Mock\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    )?;
+    stream.write_all(&response.body)?;
+    stream.flush()
+}
+
+/// Decode `%XX` escapes in a URL component; `+` is a space only in query strings
+fn percent_decode(s: &str, plus_as_space: bool) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(b)) => {
+                decoded.push(b);
+                i += 3;
+                continue;
+            }
+            (b'+', _) if plus_as_space => decoded.push(b' '),
+            (b, _) => decoded.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Parse hexadecimal number from query parameter
+fn hex_param(request: &HttpRequest, key: &str) -> Option<u16> {
+    request
+        .query
+        .get(key)
+        .and_then(|v| u16::from_str_radix(v.trim_start_matches("0x"), 16).ok())
+}
+
+/// Copy PRG data to RAM at its load address
+fn load_prg(state: &mut MockState, data: &[u8]) -> HttpResponse {
+    match extract_load_address(data) {
+        Ok(address) => {
+            let start = address as usize;
+            let end = (start + data.len() - 2).min(RAM_SIZE);
+            state.ram[start..end].copy_from_slice(&data[2..2 + end - start]);
+            HttpResponse::ok()
+        }
+        Err(e) => HttpResponse::error(400, &e.to_string()),
+    }
+}
+
+fn route(request: &HttpRequest, state: &mut MockState) -> HttpResponse {
+    let method = request.method.as_str();
+    let path = request.path.as_str();
+    match (method, path) {
+        ("GET", "version") => {
+            HttpResponse::json(200, serde_json::json!({ "version": "0.1", "errors": [] }))
+        }
+        ("GET", "info") => {
+            let mut info = serde_json::to_value(&state.info).unwrap_or_default();
+            info["errors"] = serde_json::json!([]);
+            HttpResponse::json(200, info)
+        }
+        ("GET", "drives") => {
+            let drives: Vec<_> = state
+                .drives
+                .iter()
+                .map(|(name, drive)| serde_json::json!({ name: drive }))
+                .collect();
+            HttpResponse::json(200, serde_json::json!({ "drives": drives, "errors": [] }))
+        }
+        ("GET", "machine:readmem") => {
+            let address = hex_param(request, "address").unwrap_or(0) as usize;
+            let length = request
+                .query
+                .get("length")
+                .and_then(|v| v.parse::<usize>().ok())
+                .unwrap_or(256);
+            if address + length > RAM_SIZE {
+                return HttpResponse::error(400, "Address range exceeds memory");
+            }
+            HttpResponse::bytes(state.ram[address..address + length].to_vec())
+        }
+        ("POST", "machine:writemem") => {
+            let address = hex_param(request, "address").unwrap_or(0) as usize;
+            if address + request.body.len() > RAM_SIZE {
+                return HttpResponse::error(400, "Address range exceeds memory");
+            }
+            state.ram[address..address + request.body.len()].copy_from_slice(&request.body);
+            HttpResponse::ok()
+        }
+        (
+            "PUT",
+            "machine:reset" | "machine:reboot" | "machine:poweroff" | "machine:menu_button",
+        ) => HttpResponse::ok(),
+        ("PUT", "machine:pause") => {
+            state.paused = true;
+            HttpResponse::ok()
+        }
+        ("PUT", "machine:resume") => {
+            state.paused = false;
+            HttpResponse::ok()
+        }
+        ("GET", "machine:debugreg") => HttpResponse::json(
+            200,
+            serde_json::json!({ "value": format!("{:02X}", state.debugreg), "errors": [] }),
+        ),
+        ("PUT", "machine:debugreg") => {
+            match request
+                .query
+                .get("value")
+                .map(|v| u8::from_str_radix(v, 16))
+            {
+                Some(Ok(value)) => {
+                    state.debugreg = value;
+                    HttpResponse::ok()
+                }
+                _ => HttpResponse::error(400, "Invalid value"),
+            }
+        }
+        ("POST", "runners:load_prg" | "runners:run_prg") => load_prg(state, &request.body),
+        ("POST", "runners:run_crt" | "runners:sidplay" | "runners:modplay") => HttpResponse::ok(),
+        ("PUT", p) if p.starts_with("runners:") => match request.query.get("file") {
+            Some(_) => HttpResponse::ok(),
+            None => HttpResponse::error(400, "Missing file parameter"),
+        },
+        ("PUT", p) if p.starts_with("streams/") => HttpResponse::ok(),
+        ("PUT", p) if p.starts_with("files/") && p.contains(":create_") => HttpResponse::ok(),
+        (_, p) if p.starts_with("drives/") => route_drive(request, state),
+        (_, p) if p.starts_with("configs") => route_config(request, state),
+        _ => HttpResponse::error(404, "Not found"),
+    }
+}
+
+fn route_drive(request: &HttpRequest, state: &mut MockState) -> HttpResponse {
+    let Some((name, action)) = request.path["drives/".len()..].split_once(':') else {
+        return HttpResponse::error(404, "Not found");
+    };
+    let Some(drive) = state.drives.get_mut(name) else {
+        return HttpResponse::error(404, "Drive not found");
+    };
+    match (request.method.as_str(), action) {
+        ("PUT", "mount") => {
+            let Some(image) = request.query.get("image") else {
+                return HttpResponse::error(400, "Missing image parameter");
+            };
+            let (path, file) = image.rsplit_once('/').unwrap_or(("", image));
+            drive.image_path = Some(path.to_string());
+            drive.image_file = Some(file.to_string());
+        }
+        ("POST", "mount") => {
+            let body = String::from_utf8_lossy(&request.body);
+            let file = body
+                .split("filename=\"")
+                .nth(1)
+                .and_then(|s| s.split('"').next())
+                .unwrap_or("upload");
+            drive.image_path = Some("/Temp".to_string());
+            drive.image_file = Some(file.to_string());
+        }
+        ("PUT", "remove") => {
+            drive.image_path = None;
+            drive.image_file = None;
+        }
+        ("PUT", "on") => drive.enabled = true,
+        ("PUT", "off") => drive.enabled = false,
+        ("PUT", "reset" | "unlink") => {}
+        ("PUT", "set_mode") => {
+            drive.drive_type = match request.query.get("mode").map(String::as_str) {
+                Some("1541") => Some(DriveType::CBM1541),
+                Some("1571") => Some(DriveType::CBM1571),
+                Some("1581") => Some(DriveType::CBM1581),
+                _ => return HttpResponse::error(400, "Invalid mode"),
+            }
+        }
+        ("POST", "load_rom") => drive.rom = Some(format!("custom ({} bytes)", request.body.len())),
+        _ => return HttpResponse::error(404, "Not found"),
+    }
+    HttpResponse::ok()
+}
+
+fn route_config(request: &HttpRequest, state: &mut MockState) -> HttpResponse {
+    let segments: Vec<&str> = request.path.splitn(3, '/').collect();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["configs"]) => {
+            let categories: Vec<_> = state.config.keys().collect();
+            HttpResponse::json(
+                200,
+                serde_json::json!({ "categories": categories, "errors": [] }),
+            )
+        }
+        ("POST", ["configs"]) => {
+            let Ok(changes) = serde_json::from_slice::<Configuration>(&request.body) else {
+                return HttpResponse::error(400, "Invalid JSON");
+            };
+            for (category, items) in changes {
+                state.config.entry(category).or_default().extend(items);
+            }
+            HttpResponse::ok()
+        }
+        ("PUT", ["configs:save_to_flash"]) => {
+            state.flash = state.config.clone();
+            HttpResponse::ok()
+        }
+        ("PUT", ["configs:load_from_flash"]) => {
+            state.config = state.flash.clone();
+            HttpResponse::ok()
+        }
+        ("PUT", ["configs:reset_to_default"]) => {
+            state.config = MockState::default().config;
+            HttpResponse::ok()
+        }
+        ("GET", ["configs", category]) => match state.config.get(*category) {
+            Some(items) => {
+                HttpResponse::json(200, serde_json::json!({ *category: items, "errors": [] }))
+            }
+            None => HttpResponse::error(404, "Category not found"),
+        },
+        ("GET", ["configs", category, item]) => {
+            match state.config.get(*category).and_then(|c| c.get(*item)) {
+                Some(value) => HttpResponse::json(
+                    200,
+                    serde_json::json!({ *category: { *item: { "current": value } }, "errors": [] }),
+                ),
+                None => HttpResponse::error(404, "Item not found"),
+            }
+        }
+        ("PUT", ["configs", category, item]) => {
+            let Some(value) = request.query.get("value") else {
+                return HttpResponse::error(400, "Missing value parameter");
+            };
+            match state
+                .config
+                .get_mut(*category)
+                .and_then(|c| c.get_mut(*item))
+            {
+                Some(current) => {
+                    *current = ConfigValue::from(value.as_str()).with_type_of(current);
+                    HttpResponse::ok()
+                }
+                None => HttpResponse::error(404, "Item not found"),
+            }
+        }
+        _ => HttpResponse::error(404, "Not found"),
+    }
+}
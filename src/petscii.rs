@@ -152,6 +152,30 @@ impl Petscii {
         self.0.len() == 0
     }
 
+    /// Screen codes as stored in video memory for the PETSCII characters
+    ///
+    /// # Examples
+    /// ```
+    /// use ultimate64::petscii::Petscii;
+    /// let petscii = Petscii::from_str_lossy("ready.");
+    /// assert_eq!(petscii.to_screen_codes(), [0x12, 0x05, 0x01, 0x04, 0x19, 0x2e]);
+    /// ```
+    pub fn to_screen_codes(&self) -> Vec<u8> {
+        self.0
+            .iter()
+            .map(|&byte| match byte {
+                0x00..=0x1f => byte + 0x80,
+                0x20..=0x3f => byte,
+                0x40..=0x5f => byte - 0x40,
+                0x60..=0x7f => byte - 0x20,
+                0x80..=0x9f => byte + 0x40,
+                0xa0..=0xbf => byte - 0x40,
+                0xc0..=0xfe => byte - 0x80,
+                0xff => 0x5e,
+            })
+            .collect()
+    }
+
     // Copy this Petscii string into the provided byte slice, padding any remaining
     // bytes. Return Err(PetsciiError::BufferExceeded) if the destination buffer is
     // not large enough to hold the Petscii bytes.
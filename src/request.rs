@@ -0,0 +1,757 @@
+//! # Request building and response checking
+//!
+//! Describes every REST call as a plain [`Request`] so that the blocking
+//! [`Rest`](crate::Rest) and the asynchronous `AsyncRest` clients send
+//! exactly the same requests and interpret responses the same way.
+
+use crate::{
+    auxiliary::{
+        self, check_address_overflow, check_data_overflow, encode_device_path, encode_path_segment,
+    },
+    capabilities::Capabilities,
+    config::{ConfigCategory, ConfigItem, ConfigResponse, ConfigValue, Configuration},
+    drives::{DiskImageType, Drive, DriveId, DriveList, DriveType, MountMode},
+    petscii::Petscii,
+    DeviceInfo, Error, Result, StreamType,
+};
+use log::{debug, warn};
+use reqwest::{
+    header::{HeaderMap, HeaderValue},
+    Method, StatusCode,
+};
+use std::{collections::HashMap, time::Duration};
+use url::Host;
+
+/// Body of a request
+#[derive(Debug, Clone)]
+pub(crate) enum Payload {
+    /// No body
+    Empty,
+    /// Raw bytes, e.g. a PRG file
+    Bytes(Vec<u8>),
+    /// Serialized JSON
+    Json(Vec<u8>),
+    /// Multipart form with a single file and text fields
+    Form {
+        /// File name sent with the file part
+        file_name: String,
+        /// File content
+        data: Vec<u8>,
+        /// Additional text fields
+        fields: Vec<(&'static str, String)>,
+    },
+}
+
+/// HTTP request relative to the `/v1` prefix of a device
+#[derive(Debug, Clone)]
+pub(crate) struct Request {
+    /// HTTP method
+    pub method: Method,
+    /// Path and query, e.g. `machine:readmem?address=400&length=10`
+    pub path: String,
+    /// Body
+    pub payload: Payload,
+    /// Whether sending the request twice has the same effect as sending it once
+    pub idempotent: bool,
+}
+
+impl Request {
+    /// GET request
+    pub fn get<S: Into<String>>(path: S) -> Self {
+        Self {
+            method: Method::GET,
+            path: path.into(),
+            payload: Payload::Empty,
+            idempotent: true,
+        }
+    }
+
+    /// PUT request without body
+    pub fn put<S: Into<String>>(path: S) -> Self {
+        Self {
+            method: Method::PUT,
+            path: path.into(),
+            payload: Payload::Empty,
+            idempotent: false,
+        }
+    }
+
+    /// POST request with raw bytes
+    pub fn post<S: Into<String>>(path: S, data: &[u8]) -> Self {
+        Self {
+            method: Method::POST,
+            path: path.into(),
+            payload: Payload::Bytes(data.to_vec()),
+            idempotent: false,
+        }
+    }
+
+    /// POST request with JSON body
+    pub fn post_json<S: Into<String>, T: serde::Serialize>(path: S, body: &T) -> Result<Self> {
+        Ok(Self {
+            method: Method::POST,
+            path: path.into(),
+            payload: Payload::Json(serde_json::to_vec(body)?),
+            idempotent: false,
+        })
+    }
+
+    /// Mark as safe to repeat, i.e. setting state rather than triggering an action
+    ///
+    /// GET requests are idempotent by default; actions such as a reset or
+    /// starting a program must not be, as a retry after a timeout would run
+    /// them a second time.
+    pub fn idempotent(mut self) -> Self {
+        self.idempotent = true;
+        self
+    }
+}
+
+/// Retry settings for idempotent requests
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Retry {
+    /// Number of retries after the first attempt
+    pub retries: u32,
+    /// Delay before the first retry; doubled for each following retry
+    pub backoff: Duration,
+}
+
+impl Retry {
+    /// No retries
+    pub const NONE: Self = Self {
+        retries: 0,
+        backoff: Duration::ZERO,
+    };
+
+    /// Whether failures of `request` may be retried at all
+    pub fn applies_to(&self, request: &Request) -> bool {
+        self.retries > 0 && request.idempotent
+    }
+
+    /// Delay before retry number `attempt` (counting from zero), or `None` if
+    /// the request should not be sent again
+    pub fn delay(&self, attempt: u32, error: &Error) -> Option<Duration> {
+        let retry = attempt < self.retries && matches!(error, Error::Network(_));
+        retry.then(|| self.backoff.saturating_mul(2u32.saturating_pow(attempt)))
+    }
+}
+
+/// Splitting of memory transfers into several requests
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Transfer {
+    /// Largest number of bytes read or written per request
+    pub chunk_size: u16,
+    /// Number of requests in flight at the same time
+    pub connections: usize,
+}
+
+impl Transfer {
+    /// 4 KiB chunks, one at a time
+    pub const DEFAULT: Self = Self {
+        chunk_size: 0x1000,
+        connections: 1,
+    };
+
+    /// Address and length of each chunk when transferring `length` bytes from `address`
+    pub fn chunks(&self, address: u16, length: usize) -> impl Iterator<Item = (u16, u16)> {
+        let chunk_size = self.chunk_size.max(1) as usize;
+        (0..length).step_by(chunk_size).map(move |offset| {
+            let chunk_length = chunk_size.min(length - offset);
+            (address.wrapping_add(offset as u16), chunk_length as u16)
+        })
+    }
+}
+
+/// Base URL and headers for a single device
+#[derive(Debug, Clone)]
+pub(crate) struct Target {
+    /// URL prefix, e.g. `http://192.168.1.10/v1`
+    url_prefix: String,
+    /// Headers sent with every request
+    pub headers: HeaderMap,
+    /// Retry settings
+    pub retry: Retry,
+    /// Chunking of memory transfers
+    pub transfer: Transfer,
+}
+
+impl Target {
+    /// New target from host, optional non-default port, and optional network password
+    pub fn new(host: &Host, port: Option<u16>, password: Option<String>) -> Result<Self> {
+        let mut headers = HeaderMap::default();
+        if let Some(pw) = password {
+            headers.insert("X-password", HeaderValue::from_str(pw.as_str())?);
+        }
+        let url_prefix = match port {
+            Some(port) => format!("http://{host}:{port}/v1"),
+            None => format!("http://{host}/v1"),
+        };
+        Ok(Self {
+            url_prefix,
+            headers,
+            retry: Retry::NONE,
+            transfer: Transfer::DEFAULT,
+        })
+    }
+
+    /// Full URL for a request
+    pub fn url(&self, request: &Request) -> String {
+        format!("{}/{}", self.url_prefix, request.path)
+    }
+}
+
+/// Error messages reported in the JSON body of a failed request
+#[derive(Debug, Clone, serde::Deserialize)]
+struct DeviceErrors {
+    /// Error messages
+    #[serde(default)]
+    errors: Vec<String>,
+}
+
+/// Raw debug register response from the Ultimate
+#[derive(Debug, Clone, serde::Deserialize)]
+pub(crate) struct DebugRegister {
+    /// Register value as hexadecimal string
+    value: String,
+}
+
+/// Raw version response from the Ultimate
+#[derive(Debug, Clone, serde::Deserialize)]
+pub(crate) struct ApiVersion {
+    /// REST API version, e.g. `0.1`
+    version: String,
+}
+
+/// Capabilities from the version response and the result of asking for device information
+///
+/// Firmware predating `/v1/info` answers 404 or 501, which leaves the product unknown.
+pub(crate) fn decode_capabilities(
+    version: ApiVersion,
+    info: Result<DeviceInfo>,
+) -> Result<Capabilities> {
+    let info = match info {
+        Ok(info) => Some(info),
+        Err(Error::Unsupported | Error::Device { status: 404, .. }) => None,
+        Err(error) => return Err(error),
+    };
+    let capabilities = Capabilities::new(&version.version, info.as_ref());
+    debug!("Device capabilities: {capabilities:?}");
+    Ok(capabilities)
+}
+
+/// Typed error for an unsuccessful response with `status` and response `body`
+pub(crate) fn status_error(status: StatusCode, body: &str) -> Error {
+    match status {
+        StatusCode::FORBIDDEN => Error::Auth,
+        StatusCode::NOT_IMPLEMENTED => Error::Unsupported,
+        status => {
+            let errors = serde_json::from_str::<DeviceErrors>(body)
+                .map(|e| e.errors)
+                .unwrap_or_else(|_| {
+                    Some(body.trim().to_string())
+                        .into_iter()
+                        .filter(|s| !s.is_empty())
+                        .collect()
+                });
+            Error::Device {
+                status: status.as_u16(),
+                errors,
+            }
+        }
+    }
+}
+
+pub(crate) fn info() -> Request {
+    Request::get("info")
+}
+
+pub(crate) fn version() -> Request {
+    Request::get("version")
+}
+
+/// Interval between attempts when waiting for the device or the READY prompt
+pub(crate) const ONLINE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Whether a failed request means that the device is still starting up
+pub(crate) fn is_offline(error: &Error) -> bool {
+    match error {
+        Error::Network(_) => true,
+        Error::Device { status, .. } => *status >= 500,
+        _ => false,
+    }
+}
+
+pub(crate) fn online_timeout(timeout: Duration, error: Error) -> Error {
+    Error::Timeout(format!("device not online after {timeout:?} ({error})"))
+}
+
+/// Screen memory of the default text screen
+pub(crate) const SCREEN_ADDRESS: u16 = 0x0400;
+/// Number of characters on the text screen
+pub(crate) const SCREEN_SIZE: u16 = 40 * 25;
+/// Zero page range from cursor blink flag ($CC) to cursor row ($D6)
+pub(crate) const CURSOR_STATE: (u16, u16) = (0xcc, 11);
+
+/// Check if BASIC waits for input right below a `READY.` prompt
+///
+/// `cursor_state` is memory from $CC (zero when the cursor blinks) to $D6 (cursor row).
+pub(crate) fn screen_ready(cursor_state: &[u8], screen: &[u8]) -> bool {
+    const READY: [u8; 6] = [0x12, 0x05, 0x01, 0x04, 0x19, 0x2e]; // "READY." as screen codes
+    let (Some(&blink), Some(&row)) = (cursor_state.first(), cursor_state.get(10)) else {
+        return false;
+    };
+    let prompt_row = row as usize;
+    blink == 0
+        && prompt_row > 0
+        && screen
+            .get((prompt_row - 1) * 40..)
+            .is_some_and(|line| line.starts_with(&READY))
+}
+
+pub(crate) fn ready_timeout(timeout: Duration) -> Error {
+    Error::Timeout(format!("READY prompt not seen within {timeout:?}"))
+}
+
+pub(crate) fn drives() -> Request {
+    Request::get("drives")
+}
+
+pub(crate) fn load_prg(data: &[u8]) -> Request {
+    debug!("Load PRG file of {} bytes", data.len());
+    Request::post("runners:load_prg", data)
+}
+
+pub(crate) fn run_prg(data: &[u8]) -> Request {
+    debug!("Run PRG file of {} bytes", data.len());
+    Request::post("runners:run_prg", data)
+}
+
+pub(crate) fn run_crt(data: &[u8]) -> Request {
+    debug!("Run CRT file of {} bytes", data.len());
+    Request::post("runners:run_crt", data)
+}
+
+pub(crate) fn load_remote_prg(path: &str) -> Request {
+    debug!("Load PRG file {path} on device");
+    Request::put(format!(
+        "runners:load_prg?file={}",
+        encode_path_segment(path)
+    ))
+}
+
+pub(crate) fn run_remote_prg(path: &str) -> Request {
+    debug!("Run PRG file {path} on device");
+    Request::put(format!(
+        "runners:run_prg?file={}",
+        encode_path_segment(path)
+    ))
+}
+
+pub(crate) fn run_remote_crt(path: &str) -> Request {
+    debug!("Run CRT file {path} on device");
+    Request::put(format!(
+        "runners:run_crt?file={}",
+        encode_path_segment(path)
+    ))
+}
+
+pub(crate) fn sid_play(data: &[u8], songnr: Option<u8>) -> Request {
+    let path = match songnr {
+        Some(songnr) => format!("runners:sidplay?songnr={songnr}"),
+        None => "runners:sidplay".to_string(),
+    };
+    Request::post(path, data)
+}
+
+pub(crate) fn mod_play(data: &[u8]) -> Request {
+    Request::post("runners:modplay", data)
+}
+
+pub(crate) fn sid_play_remote(path: &str, songnr: Option<u8>) -> Request {
+    let mut query = format!("runners:sidplay?file={}", encode_path_segment(path));
+    if let Some(songnr) = songnr {
+        query.push_str(&format!("&songnr={songnr}"));
+    }
+    Request::put(query)
+}
+
+pub(crate) fn mod_play_remote(path: &str) -> Request {
+    Request::put(format!(
+        "runners:modplay?file={}",
+        encode_path_segment(path)
+    ))
+}
+
+pub(crate) fn menu() -> Request {
+    debug!("Emulating menu button press");
+    Request::put("machine:menu_button")
+}
+
+pub(crate) fn reset() -> Request {
+    debug!("Reset machine");
+    Request::put("machine:reset")
+}
+
+pub(crate) fn reboot() -> Request {
+    debug!("Reboot machine");
+    Request::put("machine:reboot")
+}
+
+pub(crate) fn pause() -> Request {
+    debug!("Pause machine");
+    Request::put("machine:pause").idempotent()
+}
+
+pub(crate) fn resume() -> Request {
+    debug!("Resume machine");
+    Request::put("machine:resume").idempotent()
+}
+
+pub(crate) fn poweroff() -> Request {
+    debug!("Poweroff machine");
+    Request::put("machine:poweroff")
+}
+
+pub(crate) fn read_debugreg() -> Request {
+    Request::get("machine:debugreg")
+}
+
+pub(crate) fn write_debugreg(value: u8) -> Request {
+    debug!("Write {value:#04x} to debug register");
+    Request::put(format!("machine:debugreg?value={value:02x}")).idempotent()
+}
+
+/// Interval between debug register reads when waiting for a value
+pub(crate) const DEBUGREG_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+pub(crate) fn decode_debugreg(register: DebugRegister) -> Result<u8> {
+    u8::from_str_radix(&register.value, 16).map_err(|e| {
+        Error::decode(format!(
+            "invalid debug register value {}: {e}",
+            register.value
+        ))
+    })
+}
+
+pub(crate) fn debugreg_timeout(value: u8, last: u8) -> Error {
+    Error::Timeout(format!(
+        "debug register value {value:#04x} not seen (last read {last:#04x})"
+    ))
+}
+
+/// Warn if DMA is asked to access the CPU port
+fn warn_cpu_port(address: u16) {
+    if matches!(address, 0 | 1) {
+        warn!("DMA cannot access internal CPU registers at address 0 and 1");
+    }
+}
+
+pub(crate) fn write_mem(address: u16, data: &[u8]) -> Result<Request> {
+    check_data_overflow(address, data.len())?;
+    warn_cpu_port(address);
+    debug!("Write {} byte(s) to {:#06x}", data.len(), address);
+    Ok(Request::post(format!("machine:writemem?address={address:x}"), data).idempotent())
+}
+
+/// Check that a memory read returned the requested number of bytes
+pub(crate) fn check_read_length(address: u16, length: u16, bytes: &[u8]) -> Result<()> {
+    if bytes.len() == length as usize {
+        return Ok(());
+    }
+    Err(Error::decode(format!(
+        "expected {length} byte(s) from {address:#06x}, got {}",
+        bytes.len()
+    )))
+}
+
+pub(crate) fn read_mem(address: u16, length: u16) -> Result<Request> {
+    check_address_overflow(address, length)?;
+    warn_cpu_port(address);
+    debug!("Read {length} byte(s) from {address:#06x}");
+    Ok(Request::get(format!(
+        "machine:readmem?address={address:x}&length={length}"
+    )))
+}
+
+/// Little endian word from a two byte memory read
+pub(crate) fn decode_le_word(address: u16, bytes: Vec<u8>) -> Result<u16> {
+    let bytes: [u8; 2] = bytes
+        .try_into()
+        .map_err(|_| Error::decode(format!("failed to read from {address:#06x}")))?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+/// Destination address and bytes to write for [`Rest::load_data`](crate::Rest::load_data)
+pub(crate) fn load_data(data: &[u8], address: Option<u16>) -> Result<(u16, &[u8])> {
+    match address {
+        Some(address) => Ok((address, data)),
+        None => {
+            let load_address = auxiliary::extract_load_address(data)?;
+            Ok((load_address, &data[2..])) // skip first two bytes
+        }
+    }
+}
+
+// From the C64 Programmers Reference Guide, page 315-316:
+const KEYBOARD_LSTX: u16 = 0xc5; // Current key pressed (64 = no key pressed)
+const KEYBOARD_NDX: u16 = 0xc6; // Number of characters in keyboard buffer
+const KEYBOARD_BUFFER: u16 = 0x277; // Keyboard buffer queue (10 bytes)
+
+/// Delay after each keyboard buffer chunk (may not be needed)
+pub(crate) const TYPING_DELAY: Duration = Duration::from_millis(20);
+
+/// Delay after reset before typing into a freshly mounted disk
+pub(crate) const RESET_DELAY: Duration = Duration::from_secs(3);
+
+/// Keyboard input to load and run the first program on a disk
+pub(crate) const RUN_DISK_TEXT: &str = "load\"*\",8,1\nrun\n";
+
+/// BASIC load address on C64
+const BASIC_LOAD_ADDRESS: u16 = 0x0801;
+
+/// Keyboard input to start a program loaded at `address`: `RUN` for BASIC, else `SYS`
+pub(crate) fn run_loaded_text(address: u16) -> String {
+    match address {
+        BASIC_LOAD_ADDRESS => "run\n".to_string(),
+        address => format!("sys{address}\n"),
+    }
+}
+
+/// Memory writes emulating typing of `s`, grouped by keyboard buffer chunk
+///
+/// The C64 input buffer is limited to 10 characters, so each group clears
+/// the buffer, writes up to 10 PETSCII bytes, and triggers typing.
+pub(crate) fn type_text(s: &str) -> Vec<[(u16, Vec<u8>); 3]> {
+    debug!("Emulating keyboard typing: {s}");
+    let petscii: Vec<u8> = s
+        .chars()
+        .map(|c| Petscii::from_str_lossy(&c.to_string())[0])
+        .collect();
+    petscii
+        .chunks(10)
+        .map(|chunk| {
+            [
+                (KEYBOARD_LSTX, vec![0, 0]),
+                (KEYBOARD_BUFFER, chunk.to_vec()),
+                (KEYBOARD_NDX, vec![chunk.len() as u8]),
+            ]
+        })
+        .collect()
+}
+
+pub(crate) fn decode_drive_list(list: DriveList) -> HashMap<String, Drive> {
+    list.drives
+        .into_iter()
+        .flat_map(|m| m.into_iter())
+        .collect()
+}
+
+pub(crate) fn find_drive(mut drives: HashMap<String, Drive>, drive: DriveId) -> Result<Drive> {
+    drives
+        .remove(&drive.to_string())
+        .ok_or_else(|| Error::invalid(format!("drive {drive} not found")))
+}
+
+pub(crate) fn mount_disk_image(
+    file_name: String,
+    data: Vec<u8>,
+    drive: &str,
+    mount_mode: MountMode,
+) -> Result<Request> {
+    let disktype = DiskImageType::from_file_name(&file_name)?;
+    debug!("Mount {file_name} in drive {drive}");
+    Ok(Request {
+        method: Method::POST,
+        path: format!("drives/{drive}:mount"),
+        payload: Payload::Form {
+            file_name,
+            data,
+            fields: vec![
+                ("mode", mount_mode.to_string()),
+                ("type", disktype.to_string()),
+            ],
+        },
+        idempotent: false,
+    })
+}
+
+pub(crate) fn mount_remote_image(
+    path: &str,
+    drive: &str,
+    mount_mode: MountMode,
+) -> Result<Request> {
+    let disktype = DiskImageType::from_file_name(path)?;
+    debug!("Mount {path} on device in drive {drive}");
+    Ok(Request::put(format!(
+        "drives/{drive}:mount?image={}&mode={mount_mode}&type={disktype}",
+        encode_path_segment(path)
+    ))
+    .idempotent())
+}
+
+/// Check ROM size against the type of `drive`, described by `status`
+pub(crate) fn load_drive_rom(drive: DriveId, status: &Drive, rom: &[u8]) -> Result<Request> {
+    let drive_type = status
+        .drive_type
+        .as_ref()
+        .ok_or_else(|| Error::decode(format!("unknown type of drive {drive}")))?;
+    drive_type.check_rom_size(rom.len())?;
+    debug!("Load ROM of {} bytes to drive {drive}", rom.len());
+    Ok(Request::post(format!("drives/{drive}:load_rom"), rom))
+}
+
+pub(crate) fn drive_on(drive: DriveId) -> Request {
+    debug!("Turn on drive {drive}");
+    Request::put(format!("drives/{drive}:on")).idempotent()
+}
+
+pub(crate) fn drive_off(drive: DriveId) -> Request {
+    debug!("Turn off drive {drive}");
+    Request::put(format!("drives/{drive}:off")).idempotent()
+}
+
+pub(crate) fn drive_reset(drive: DriveId) -> Request {
+    debug!("Reset drive {drive}");
+    Request::put(format!("drives/{drive}:reset"))
+}
+
+pub(crate) fn drive_eject(drive: DriveId) -> Request {
+    debug!("Eject disk from drive {drive}");
+    Request::put(format!("drives/{drive}:remove"))
+}
+
+pub(crate) fn drive_unlink(drive: DriveId) -> Request {
+    debug!("Unlink disk in drive {drive}");
+    Request::put(format!("drives/{drive}:unlink"))
+}
+
+pub(crate) fn drive_set_mode(drive: DriveId, mode: DriveType) -> Result<Request> {
+    if mode == DriveType::DOS {
+        return Err(Error::invalid(
+            "drive mode must be one of: 1541, 1571, 1581",
+        ));
+    }
+    debug!("Set drive {drive} mode to {mode}");
+    Ok(Request::put(format!("drives/{drive}:set_mode?mode={mode}")).idempotent())
+}
+
+pub(crate) fn create_disk_image(
+    path: &str,
+    kind: DiskImageType,
+    name: Option<&str>,
+    tracks: Option<u8>,
+) -> Result<Request> {
+    use DiskImageType::*;
+    match (&kind, tracks) {
+        (D64, None | Some(35 | 40)) => {}
+        (D64, Some(t)) => {
+            return Err(Error::invalid(format!(
+                "D64 images must have 35 or 40 tracks, not {t}"
+            )))
+        }
+        (D71 | D81, None) => {}
+        (D71 | D81, Some(_)) => {
+            return Err(Error::invalid(format!(
+                "track count cannot be set for {kind} images"
+            )))
+        }
+        (DNP, Some(1..)) => {}
+        (DNP, _) => return Err(Error::invalid("DNP images require a track count of 1-255")),
+        (G64 | G71, _) => return Err(Error::invalid(format!("{kind} images cannot be created"))),
+    }
+    let mut params = Vec::new();
+    if let Some(tracks) = tracks {
+        params.push(format!("tracks={tracks}"));
+    }
+    if let Some(name) = name {
+        params.push(format!("diskname={}", encode_path_segment(name)));
+    }
+    let mut url = format!("files/{}:create_{kind}", encode_device_path(path));
+    if !params.is_empty() {
+        url = format!("{url}?{}", params.join("&"));
+    }
+    debug!("Create {kind} image {path} on device");
+    Ok(Request::put(url))
+}
+
+pub(crate) fn start_stream(host: &Host, port: u16, kind: StreamType) -> Request {
+    Request::put(format!("streams/{kind}:start?ip={host}:{port}"))
+}
+
+pub(crate) fn stop_stream(kind: StreamType) -> Request {
+    Request::put(format!("streams/{kind}:stop"))
+}
+
+pub(crate) fn config_categories() -> Request {
+    Request::get("configs")
+}
+
+pub(crate) fn config_category(category: &str) -> Request {
+    Request::get(format!("configs/{}", encode_path_segment(category)))
+}
+
+pub(crate) fn decode_config_category(
+    response: ConfigResponse<ConfigValue>,
+    category: &str,
+) -> Result<ConfigCategory> {
+    response
+        .categories
+        .into_values()
+        .next()
+        .ok_or_else(|| Error::invalid(format!("configuration category not found: {category}")))
+}
+
+pub(crate) fn config_item(category: &str, item: &str) -> Request {
+    Request::get(format!(
+        "configs/{}/{}",
+        encode_path_segment(category),
+        encode_path_segment(item)
+    ))
+}
+
+pub(crate) fn decode_config_item(
+    response: ConfigResponse<ConfigItem>,
+    category: &str,
+    item: &str,
+) -> Result<ConfigItem> {
+    response
+        .categories
+        .into_values()
+        .flat_map(|items| items.into_values())
+        .next()
+        .ok_or_else(|| Error::invalid(format!("configuration item not found: {category} / {item}")))
+}
+
+pub(crate) fn set_config_item(category: &str, item: &str, value: &ConfigValue) -> Request {
+    debug!("Set configuration {category} / {item} = {value}");
+    Request::put(format!(
+        "configs/{}/{}?value={}",
+        encode_path_segment(category),
+        encode_path_segment(item),
+        encode_path_segment(&value.to_string())
+    ))
+    .idempotent()
+}
+
+pub(crate) fn set_config_items(config: &Configuration) -> Result<Request> {
+    debug!(
+        "Set {} configuration item(s)",
+        config.values().map(|c| c.len()).sum::<usize>()
+    );
+    Ok(Request::post_json("configs", config)?.idempotent())
+}
+
+pub(crate) fn save_config_to_flash() -> Request {
+    debug!("Save configuration to flash");
+    Request::put("configs:save_to_flash")
+}
+
+pub(crate) fn load_config_from_flash() -> Request {
+    debug!("Load configuration from flash");
+    Request::put("configs:load_from_flash")
+}
+
+pub(crate) fn reset_config_to_defaults() -> Request {
+    debug!("Reset configuration to defaults");
+    Request::put("configs:reset_to_default")
+}
@@ -0,0 +1,323 @@
+//! # Memory search
+//!
+//! Find byte patterns in a full read of the 64 KiB address space. A
+//! [`Pattern`] is a sequence of byte values, wildcards, or value ranges,
+//! given as hex or built from text as PETSCII or as screen codes.
+//!
+//! # Examples
+//! ~~~ rust, ignore
+//! use ultimate64::search::{Memory, Pattern};
+//! let memory = Memory::read(&ultimate)?;
+//! for address in memory.find(&Pattern::screen_codes("ready."), 0x0400..=0x07ff) {
+//!     println!("{address:#06x}");
+//! }
+//! ~~~
+
+use crate::{petscii::Petscii, Error, Result, Ultimate};
+use core::fmt::Display;
+use std::{ops::RangeInclusive, str::FromStr};
+
+/// Size of the C64 address space in bytes
+pub const MEMORY_SIZE: usize = 0x10000;
+
+/// Bytes per line in [`Memory::hexdump`]
+const HEXDUMP_WIDTH: usize = 16;
+
+/// Byte pattern where each position matches a range of values
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern(Vec<RangeInclusive<u8>>);
+
+impl Pattern {
+    /// Pattern matching exactly `bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Self::new(bytes.iter().map(|&byte| byte..=byte).collect())
+    }
+
+    /// Pattern from value ranges, one per position
+    pub fn new(ranges: Vec<RangeInclusive<u8>>) -> Result<Self> {
+        if ranges.is_empty() {
+            return Err(Error::invalid("empty search pattern"));
+        }
+        Ok(Self(ranges))
+    }
+
+    /// Parse hex bytes, `??` wildcards, and ranges such as `30-39`
+    ///
+    /// Bytes may be separated by spaces or written together.
+    ///
+    /// # Examples
+    /// ```
+    /// use ultimate64::search::Pattern;
+    /// let pattern = Pattern::hex("a9??8d 20 d0 30-39").unwrap();
+    /// assert_eq!(pattern.len(), 6);
+    /// assert_eq!(pattern.to_string(), "a9 ?? 8d 20 d0 30-39");
+    /// assert!(pattern.matches(&[0xa9, 0x06, 0x8d, 0x20, 0xd0, 0x35]));
+    /// assert!(!pattern.matches(&[0xa9, 0x06, 0x8d, 0x21, 0xd0, 0x35]));
+    /// assert!(Pattern::hex("a9 8").is_err());
+    /// ```
+    pub fn hex(s: &str) -> Result<Self> {
+        let byte = |s: &str| {
+            u8::from_str_radix(s, 16)
+                .map_err(|_| Error::invalid(format!("invalid hex byte in search pattern: {s}")))
+        };
+        let mut ranges = Vec::new();
+        for token in s.split_whitespace() {
+            if let Some((low, high)) = token.split_once('-') {
+                let (low, high) = (byte(low)?, byte(high)?);
+                if low > high {
+                    return Err(Error::invalid(format!(
+                        "empty range in search pattern: {token}"
+                    )));
+                }
+                ranges.push(low..=high);
+                continue;
+            }
+            if token.len() % 2 != 0 || !token.is_ascii() {
+                return Err(Error::invalid(format!(
+                    "search pattern needs two hex digits per byte: {token}"
+                )));
+            }
+            for i in (0..token.len()).step_by(2) {
+                ranges.push(match &token[i..i + 2] {
+                    "??" => 0..=0xff,
+                    digits => byte(digits).map(|byte| byte..=byte)?,
+                });
+            }
+        }
+        Self::new(ranges)
+    }
+
+    /// Text encoded as PETSCII with [`Petscii::from_str_lossy`]
+    pub fn petscii(text: &str) -> Result<Self> {
+        Self::from_bytes(Petscii::from_str_lossy(text).as_bytes())
+    }
+
+    /// Text encoded as screen codes, as found in video memory
+    ///
+    /// # Examples
+    /// ```
+    /// use ultimate64::search::Pattern;
+    /// assert_eq!(Pattern::screen_codes("ready.").unwrap().to_string(), "12 05 01 04 19 2e");
+    /// ```
+    pub fn screen_codes(text: &str) -> Result<Self> {
+        Self::from_bytes(&Petscii::from_str_lossy(text).to_screen_codes())
+    }
+
+    /// Number of bytes matched
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Always false as patterns cannot be empty
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// True if `data` starts with bytes matching the pattern
+    pub fn matches(&self, data: &[u8]) -> bool {
+        data.len() >= self.len()
+            && self
+                .0
+                .iter()
+                .zip(data)
+                .all(|(range, byte)| range.contains(byte))
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = Error;
+
+    /// Parse hex pattern; see [`Pattern::hex`]
+    fn from_str(s: &str) -> Result<Self> {
+        Self::hex(s)
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, range) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            match (*range.start(), *range.end()) {
+                (0x00, 0xff) => write!(f, "??")?,
+                (low, high) if low == high => write!(f, "{low:02x}")?,
+                (low, high) => write!(f, "{low:02x}-{high:02x}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse address range as `START-END` (inclusive) or `START:LENGTH`
+///
+/// # Examples
+/// ```
+/// use ultimate64::search::parse_range;
+/// assert_eq!(parse_range("0x0400-0x07e7").unwrap(), 0x0400..=0x07e7);
+/// assert_eq!(parse_range("1024:1000").unwrap(), 0x0400..=0x07e7);
+/// assert!(parse_range("0xd000-0xc000").is_err());
+/// assert!(parse_range("0xffff:2").is_err());
+/// ```
+pub fn parse_range(s: &str) -> Result<RangeInclusive<u16>> {
+    let number = |s: &str| {
+        parse_int::parse::<u16>(s.trim())
+            .map_err(|_| Error::invalid(format!("invalid number in address range {s}")))
+    };
+    let (start, end) = if let Some((start, end)) = s.split_once('-') {
+        (number(start)?, number(end)?)
+    } else if let Some((start, length)) = s.split_once(':') {
+        let (start, length) = (number(start)?, number(length)?);
+        let end = length
+            .checked_sub(1)
+            .and_then(|last| start.checked_add(last))
+            .ok_or_else(|| Error::invalid(format!("invalid address range {s}")))?;
+        (start, end)
+    } else {
+        return Err(Error::invalid(format!(
+            "address range must be START-END or START:LENGTH: {s}"
+        )));
+    };
+    if start > end {
+        return Err(Error::invalid(format!("empty address range {s}")));
+    }
+    Ok(start..=end)
+}
+
+/// Copy of the full 64 KiB address space, read once and searched locally
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Memory(Vec<u8>);
+
+impl Memory {
+    /// Read the full address space from the machine
+    pub fn read<U: Ultimate + ?Sized>(ultimate: &U) -> Result<Self> {
+        Self::read_with_progress(ultimate, &mut |_, _| {})
+    }
+
+    /// Read the full address space, calling `progress` with bytes done and total
+    pub fn read_with_progress<U: Ultimate + ?Sized>(
+        ultimate: &U,
+        progress: &mut dyn FnMut(usize, usize),
+    ) -> Result<Self> {
+        const HALF: u16 = (MEMORY_SIZE / 2) as u16;
+        let mut data = Vec::with_capacity(MEMORY_SIZE);
+        for address in [0, HALF] {
+            let offset = address as usize;
+            data.extend(
+                ultimate.read_mem_with_progress(address, HALF, &mut |done, _| {
+                    progress(offset + done, MEMORY_SIZE)
+                })?,
+            );
+        }
+        Self::from_bytes(data)
+    }
+
+    /// Memory from a 64 KiB dump indexed by address
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self> {
+        if data.len() != MEMORY_SIZE {
+            return Err(Error::invalid(format!(
+                "memory dump must be {MEMORY_SIZE} bytes, got {}",
+                data.len()
+            )));
+        }
+        Ok(Self(data))
+    }
+
+    /// All bytes, indexed by address
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Addresses in `range` where `pattern` matches, including overlapping matches
+    ///
+    /// A match must lie entirely within `range`.
+    pub fn find<'a>(
+        &'a self,
+        pattern: &'a Pattern,
+        range: RangeInclusive<u16>,
+    ) -> impl Iterator<Item = u16> + 'a {
+        let (start, end) = (*range.start() as usize, *range.end() as usize + 1);
+        (start..end)
+            .filter(move |&address| pattern.matches(&self.0[address..end]))
+            .map(|address| address as u16)
+    }
+
+    /// Hexdump of whole lines covering `range`, with addresses and PETSCII characters
+    ///
+    /// # Examples
+    /// ```
+    /// use ultimate64::search::Memory;
+    /// let mut data = vec![0; 0x10000];
+    /// data[0x1004..0x1007].copy_from_slice(b"ABC");
+    /// let memory = Memory::from_bytes(data).unwrap();
+    /// assert_eq!(
+    ///     memory.hexdump(0x1004..=0x1006),
+    ///     "1000: 00 00 00 00 41 42 43 00 00 00 00 00 00 00 00 00  ....abc.........\n"
+    /// );
+    /// ```
+    pub fn hexdump(&self, range: RangeInclusive<u16>) -> String {
+        let start = *range.start() as usize / HEXDUMP_WIDTH * HEXDUMP_WIDTH;
+        let end = *range.end() as usize + 1;
+        let mut dump = String::new();
+        for address in (start..end).step_by(HEXDUMP_WIDTH) {
+            let line = &self.0[address..address + HEXDUMP_WIDTH];
+            let hex: Vec<_> = line.iter().map(|byte| format!("{byte:02x}")).collect();
+            let text: String = Petscii::from_bytes(line)
+                .to_string()
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_graphic() || c == ' ' {
+                        c
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            dump.push_str(&format!("{address:04x}: {}  {text}\n", hex.join(" ")));
+        }
+        dump
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+
+    #[test]
+    fn test_find() {
+        let emulator = Emulator::default();
+        emulator
+            .write_mem(0x0400, &[0x12, 0x05, 0x01, 0x04, 0x19, 0x2e])
+            .unwrap();
+        emulator
+            .write_mem(0xc000, &[0xa9, 0x01, 0x8d, 0x20, 0xd0])
+            .unwrap();
+        emulator.write_mem(0xfffe, &[0xa9, 0x02]).unwrap();
+        let memory = Memory::read(&emulator).unwrap();
+
+        let ready = Pattern::screen_codes("ready.").unwrap();
+        assert_eq!(
+            memory.find(&ready, 0..=0xffff).collect::<Vec<_>>(),
+            [0x0400]
+        );
+        assert_eq!(memory.find(&ready, 0x0401..=0x07ff).count(), 0);
+        assert_eq!(memory.find(&ready, 0x0400..=0x0404).count(), 0);
+
+        let store = Pattern::hex("a9 ?? 8d").unwrap();
+        assert_eq!(
+            memory.find(&store, 0..=0xffff).collect::<Vec<_>>(),
+            [0xc000]
+        );
+        let load = Pattern::hex("a9 01-02").unwrap();
+        assert_eq!(
+            memory.find(&load, 0..=0xffff).collect::<Vec<_>>(),
+            [0xc000, 0xfffe]
+        );
+        let zeros = Pattern::from_bytes(&[0, 0]).unwrap();
+        assert_eq!(
+            memory.find(&zeros, 0x3000..=0x3002).collect::<Vec<_>>(),
+            [0x3000, 0x3001]
+        );
+    }
+}
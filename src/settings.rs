@@ -0,0 +1,179 @@
+//! # Named device profiles
+//!
+//! Connection settings for one or more devices can be kept in a TOML file,
+//! by default `~/.config/ru64/config.toml`, and selected by name:
+//!
+//! ~~~ toml
+//! default = "lab-u64"
+//!
+//! [devices.lab-u64]
+//! host = "192.168.1.64"
+//! password = "secret"
+//! drive = "b"
+//! stream = "239.0.1.65"
+//!
+//! [devices.bench-u2]
+//! host = "u2plus.local"
+//! port = 8080
+//!
+//! [groups]
+//! rack = ["lab-u64", "bench-u2"]
+//! ~~~
+
+use crate::{auxiliary, drives::DriveId, Error, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    env,
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+};
+use url::Host;
+
+/// Environment variable overriding the location of the settings file
+pub const SETTINGS_ENV: &str = "RU64_CONFIG";
+
+/// Connection settings and defaults for a single device
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceProfile {
+    /// Hostname or IP address
+    pub host: Option<String>,
+    /// Network password
+    pub password: Option<String>,
+    /// TCP port of the REST API
+    pub port: Option<u16>,
+    /// Default drive for drive and mount commands
+    pub drive: Option<DriveId>,
+    /// Multicast address for data streams
+    pub stream: Option<Ipv4Addr>,
+}
+
+impl DeviceProfile {
+    /// Parsed host, if set
+    pub fn host(&self) -> Result<Option<Host>> {
+        self.host.as_deref().map(auxiliary::parse_host).transpose()
+    }
+}
+
+/// Content of the settings file
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    /// Profile used when none is selected
+    pub default: Option<String>,
+    /// Profiles by name
+    #[serde(default)]
+    pub devices: BTreeMap<String, DeviceProfile>,
+    /// Groups of profile names
+    #[serde(default)]
+    pub groups: BTreeMap<String, Vec<String>>,
+}
+
+impl Settings {
+    /// Location of the settings file
+    ///
+    /// Taken from `RU64_CONFIG` if set; otherwise `ru64/config.toml` in the
+    /// user's configuration directory (`$XDG_CONFIG_HOME`, `~/.config`, or `%APPDATA%`).
+    pub fn path() -> Option<PathBuf> {
+        if let Some(path) = env::var_os(SETTINGS_ENV) {
+            return Some(path.into());
+        }
+        let base = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .or_else(|| env::var_os("APPDATA").map(PathBuf::from))?;
+        Some(base.join("ru64").join("config.toml"))
+    }
+
+    /// Load settings from the default location; a missing file gives empty settings
+    pub fn load() -> Result<Self> {
+        match Self::path() {
+            Some(path) if path.is_file() => Self::read(path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Read settings from TOML file
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parse settings from TOML string
+    ///
+    /// # Examples
+    /// ```
+    /// use ultimate64::{drives::DriveId, settings::Settings};
+    /// let settings = Settings::parse("[devices.lab]\nhost = \"10.0.0.64\"\ndrive = \"b\"").unwrap();
+    /// let lab = settings.profile(Some("lab")).unwrap().unwrap();
+    /// assert_eq!(lab.drive, Some(DriveId::B));
+    /// assert!(settings.profile(None).unwrap().is_none());
+    /// assert!(settings.profile(Some("office")).is_err());
+    /// ```
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(toml::from_str(s)?)
+    }
+
+    /// Profile selected by `name`, or the default profile if no name is given
+    ///
+    /// Fails if a name is given, or set as default, but no such profile exists.
+    pub fn profile(&self, name: Option<&str>) -> Result<Option<&DeviceProfile>> {
+        let Some(name) = name.or(self.default.as_deref()) else {
+            return Ok(None);
+        };
+        self.devices
+            .get(name)
+            .map(Some)
+            .ok_or_else(|| self.unknown_profile(name))
+    }
+
+    /// Profiles selected by `names`, which may include groups, or the default if `names` is empty
+    ///
+    /// # Examples
+    /// ```
+    /// use ultimate64::settings::Settings;
+    /// let settings = Settings::parse(
+    ///     "default = \"rack\"\n[devices.a]\n[devices.b]\n[groups]\nrack = [\"a\", \"b\"]",
+    /// )
+    /// .unwrap();
+    /// let rack = settings.select::<&str>(&[]).unwrap();
+    /// assert_eq!(rack.iter().map(|(name, _)| *name).collect::<Vec<_>>(), ["a", "b"]);
+    /// assert_eq!(settings.select(&["b"]).unwrap().len(), 1);
+    /// assert!(settings.select(&["c"]).is_err());
+    /// ```
+    pub fn select<S: AsRef<str>>(&self, names: &[S]) -> Result<Vec<(&str, &DeviceProfile)>> {
+        let mut names: Vec<&str> = names.iter().map(AsRef::as_ref).collect();
+        if names.is_empty() {
+            names.extend(self.default.as_deref());
+        }
+        let mut selected = Vec::new();
+        for name in names {
+            let members = match self.groups.get(name) {
+                Some(members) => members.iter().map(String::as_str).collect(),
+                None => vec![name],
+            };
+            for member in members {
+                let (name, profile) = self
+                    .devices
+                    .get_key_value(member)
+                    .ok_or_else(|| self.unknown_profile(member))?;
+                if !selected.iter().any(|(selected, _)| selected == name) {
+                    selected.push((name.as_str(), profile));
+                }
+            }
+        }
+        Ok(selected)
+    }
+
+    fn unknown_profile(&self, name: &str) -> Error {
+        let known: Vec<_> = self.devices.keys().map(String::as_str).collect();
+        Error::invalid(format!(
+            "unknown device profile {name} (available: {})",
+            if known.is_empty() {
+                "none".to_string()
+            } else {
+                known.join(", ")
+            }
+        ))
+    }
+}
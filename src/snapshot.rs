@@ -0,0 +1,537 @@
+//! # Machine snapshots
+//!
+//! A [`MachineSnapshot`] holds memory together with colour RAM and the
+//! registers of the VIC-II, SID and both CIAs. It is captured from a paused
+//! machine and can be written back later, e.g. to return to a known state
+//! during long test sequences.
+//!
+//! The CPU registers cannot be read over the REST API, so a snapshot is best
+//! taken and restored while the program runs a main loop that does not keep
+//! state in registers. SID registers are write-only on real hardware; the
+//! values stored are the shadow copies kept by the device. Snapshots
+//! converted from other sources may lack them, in which case the SID is left
+//! alone on restore.
+//!
+//! Memory is read by DMA, which sees the banking selected by the CPU port at
+//! $01 but cannot change it. Where BASIC or KERNAL ROM is banked in, the
+//! snapshot holds the ROM rather than the RAM underneath, and that area is
+//! left alone on restore; it is recognised by the ROM's signature.
+//!
+//! CIA registers are read without side effects: the interrupt control
+//! register is skipped, as reading it acknowledges pending interrupts, and
+//! the time of day clock is read from hours to tenths so its latch is
+//! released. On restore, the control registers are written first so the
+//! clock rather than the alarm is set, and the clock from hours to tenths so
+//! it runs again. The interrupt mask cannot be read and is not restored.
+//! Likewise, the VIC-II sprite collision registers at $D01E and $D01F are
+//! skipped, as reading them clears them.
+//!
+//! VICE snapshots are converted by the [`crate::vsf`] module;
+//! [`MachineSnapshot::load`] recognises them automatically.
+//!
+//! # File format
+//!
+//! Snapshot files are binary, all sections back to back without padding:
+//!
+//! | Offset | Size  | Content                                           |
+//! |--------|-------|---------------------------------------------------|
+//! | 0      | 8     | Magic `RU64SNAP`                                  |
+//! | 8      | 2     | Format version, little endian (currently 2)       |
+//! | 10     | 1     | Flags; bit 0 set if the SID registers are present |
+//! | 11     | 65536 | Memory $0000-$FFFF; the I/O area is zero          |
+//! | 65547  | 1024  | Colour RAM $D800-$DBFF                            |
+//! | 66571  | 47    | VIC-II registers $D000-$D02E; collisions are zero |
+//! | 66618  | 25    | SID registers $D400-$D418; zero if absent         |
+//! | 66643  | 16    | CIA 1 registers $DC00-$DC0F; ICR is zero          |
+//! | 66659  | 16    | CIA 2 registers $DD00-$DD0F; ICR is zero          |
+//!
+//! Version 1 files lack the flags byte and always hold the SID registers;
+//! they are still read.
+//!
+//! # Examples
+//! ~~~ rust, ignore
+//! let snapshot = ultimate.snapshot()?;
+//! snapshot.save("level3.snap")?;
+//! // ...
+//! ultimate.restore_snapshot(&MachineSnapshot::load("level3.snap")?)?;
+//! ~~~
+
+use crate::{
+    settings::Settings,
+    vsf::{self, VsfFile},
+    Error, Result, Ultimate,
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// First bytes of a snapshot file
+pub const MAGIC: &[u8; 8] = b"RU64SNAP";
+
+/// Version of the file format written by [`MachineSnapshot::to_bytes`]
+pub const FORMAT_VERSION: u16 = 2;
+
+/// File extension of snapshots in named slots
+pub const SLOT_EXTENSION: &str = "snap";
+
+/// Start of the I/O area, which is not part of the RAM section
+const IO_START: u16 = 0xd000;
+/// First address after the I/O area
+const IO_END: u16 = 0xe000;
+/// CIA 1 base address
+const CIA1: u16 = 0xdc00;
+/// CIA 2 base address
+const CIA2: u16 = 0xdd00;
+/// Time of day tenths register, relative to the CIA base address
+const CIA_TOD_TENTHS: u16 = 0x08;
+/// Time of day hours register, relative to the CIA base address
+const CIA_TOD_HOURS: u16 = 0x0b;
+/// Control register B, relative to the CIA base address
+const CIA_CRB: u16 = 0x0f;
+/// Bit in control register B directing time of day writes to the alarm
+const CIA_CRB_ALARM: u8 = 0x80;
+
+/// CIA register reads, relative to the base address: offset and length
+///
+/// Skips the interrupt control register at $0D and reads the time of day
+/// from hours to tenths, which latches and then releases the clock.
+const CIA_READS: [(u16, u16); 7] = [
+    (0x00, 8),
+    (CIA_TOD_HOURS, 1),
+    (0x0a, 1),
+    (0x09, 1),
+    (CIA_TOD_TENTHS, 1),
+    (0x0c, 1),
+    (0x0e, 2),
+];
+
+/// VIC-II register reads, relative to the base address: offset and length
+///
+/// Skips the sprite collision registers at $1E and $1F.
+const VIC_READS: [(u16, u16); 2] = [(0x00, 0x1e), (0x20, 0x0f)];
+
+/// RAM written on restore, split where ROM may be banked in: start and end
+const RAM_SEGMENTS: [(usize, usize); 4] = [
+    (0x0000, 0xa000),
+    (0xa000, 0xc000), // BASIC ROM
+    (0xc000, IO_START as usize),
+    (IO_END as usize, 0x10000), // KERNAL ROM
+];
+
+/// Segments that may hold ROM: start, signature address, and signature
+const ROM_SIGNATURES: [(usize, usize, &[u8]); 2] = [
+    (0xa000, 0xa004, b"CBMBASIC"),
+    // NMI, RESET and IRQ vectors of the KERNAL
+    (0xe000, 0xfffa, &[0x43, 0xfe, 0xe2, 0xfc, 0x48, 0xff]),
+];
+
+/// Sections following the header, in file order: address and length
+const SECTIONS: [(u16, usize); 6] = [
+    (0x0000, 0x10000), // RAM
+    (0xd800, 0x400),   // colour RAM
+    (0xd000, 0x2f),    // VIC-II
+    (0xd400, 0x19),    // SID
+    (CIA1, 0x10),      // CIA 1
+    (CIA2, 0x10),      // CIA 2
+];
+
+/// Length of the header with magic, format version and flags
+const HEADER_SIZE: usize = MAGIC.len() + 3;
+/// Flag set in the header if the SID registers are present
+const FLAG_SID: u8 = 0x01;
+/// SID section written when the registers are absent
+const NO_SID: [u8; SECTIONS[3].1] = [0; SECTIONS[3].1];
+
+/// RAM, colour RAM and I/O chip registers of a C64
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MachineSnapshot {
+    /// Memory $0000-$FFFF as seen with the banking at capture, i.e. including
+    /// BASIC and KERNAL ROM if banked in; the I/O area $D000-$DFFF is zero
+    pub ram: Vec<u8>,
+    /// Colour RAM $D800-$DBFF
+    pub color_ram: Vec<u8>,
+    /// VIC-II registers $D000-$D02E; the sprite collision registers are zero
+    pub vic: Vec<u8>,
+    /// SID registers $D400-$D418, as shadowed by the device; `None` if unknown
+    pub sid: Option<Vec<u8>>,
+    /// CIA 1 registers $DC00-$DC0F; the interrupt control register is zero
+    pub cia1: Vec<u8>,
+    /// CIA 2 registers $DD00-$DD0F; the interrupt control register is zero
+    pub cia2: Vec<u8>,
+}
+
+impl MachineSnapshot {
+    /// Pause the machine, read memory and registers, and resume
+    pub fn capture<U: Ultimate + ?Sized>(ultimate: &U) -> Result<Self> {
+        ultimate.pause()?;
+        let data = Self::reads()
+            .map(|(address, length)| ultimate.read_mem(address, length))
+            .collect::<Result<Vec<_>>>();
+        ultimate.resume()?;
+        Self::from_reads(data?)
+    }
+
+    /// Pause the machine, write memory and registers, and resume
+    pub fn restore<U: Ultimate + ?Sized>(&self, ultimate: &U) -> Result<()> {
+        let writes = self.writes()?;
+        ultimate.pause()?;
+        let result = writes
+            .into_iter()
+            .try_for_each(|(address, data)| ultimate.write_mem(address, &data));
+        ultimate.resume()?;
+        result
+    }
+
+    /// Memory reads making up a snapshot: address and length
+    pub(crate) fn reads() -> impl Iterator<Item = (u16, u16)> {
+        let ram = [(0x0000, IO_START), (IO_END, 0x2000)];
+        let [_, color_ram, vic, sid, ..] = SECTIONS;
+        ram.into_iter()
+            .chain([(color_ram.0, color_ram.1 as u16)])
+            .chain(register_reads(vic.0, &VIC_READS))
+            .chain([(sid.0, sid.1 as u16)])
+            .chain(register_reads(CIA1, &CIA_READS))
+            .chain(register_reads(CIA2, &CIA_READS))
+    }
+
+    /// Snapshot from the results of [`reads`](Self::reads)
+    pub(crate) fn from_reads(data: Vec<Vec<u8>>) -> Result<Self> {
+        let mut data = data.into_iter();
+        let mut ram = data.next().unwrap_or_default();
+        ram.resize(IO_END as usize, 0);
+        ram.extend(data.next().unwrap_or_default());
+        let color_ram = data.next().unwrap_or_default();
+        let vic = registers_from_reads(&mut data, &VIC_READS, SECTIONS[2].1)?;
+        let sid = data.next().unwrap_or_default();
+        let cia1 = registers_from_reads(&mut data, &CIA_READS, SECTIONS[4].1)?;
+        let cia2 = registers_from_reads(&mut data, &CIA_READS, SECTIONS[5].1)?;
+        let snapshot = Self {
+            ram,
+            color_ram,
+            vic,
+            sid: Some(sid),
+            cia1,
+            cia2,
+        };
+        snapshot.check()?;
+        Ok(snapshot)
+    }
+
+    /// Memory writes restoring the snapshot, in order: address and data
+    pub(crate) fn writes(&self) -> Result<Vec<(u16, Vec<u8>)>> {
+        self.check()?;
+        let mut segments: Vec<(usize, usize)> = Vec::new();
+        for (start, end) in RAM_SEGMENTS {
+            if self.holds_rom(start) {
+                continue;
+            }
+            match segments.last_mut() {
+                Some((_, last)) if *last == start => *last = end,
+                _ => segments.push((start, end)),
+            }
+        }
+        let mut writes: Vec<_> = segments
+            .into_iter()
+            .map(|(start, end)| (start as u16, self.ram[start..end].to_vec()))
+            .collect();
+        writes.push((SECTIONS[1].0, self.color_ram.clone()));
+        writes.push((SECTIONS[2].0, self.vic.clone()));
+        if let Some(sid) = &self.sid {
+            writes.push((SECTIONS[3].0, sid.clone()));
+        }
+        writes.extend(cia_writes(CIA1, &self.cia1));
+        writes.extend(cia_writes(CIA2, &self.cia2));
+        Ok(writes)
+    }
+
+    /// Whether the RAM segment at `start` holds banked in ROM rather than RAM
+    fn holds_rom(&self, start: usize) -> bool {
+        ROM_SIGNATURES.iter().any(|&(rom, address, signature)| {
+            rom == start && self.ram[address..address + signature.len()] == *signature
+        })
+    }
+
+    /// Sections in file order
+    fn sections(&self) -> [&[u8]; 6] {
+        [
+            &self.ram,
+            &self.color_ram,
+            &self.vic,
+            self.sid.as_deref().unwrap_or(&NO_SID),
+            &self.cia1,
+            &self.cia2,
+        ]
+    }
+
+    /// Fail unless every section has the expected length
+    fn check(&self) -> Result<()> {
+        for (data, (address, length)) in self.sections().into_iter().zip(SECTIONS) {
+            if data.len() != length {
+                return Err(Error::decode(format!(
+                    "snapshot section at {address:#06x} has {} bytes, expected {length}",
+                    data.len()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Encode in the snapshot file format
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        self.check()?;
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.ram.len() + 0x500);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.push(if self.sid.is_some() { FLAG_SID } else { 0 });
+        self.sections()
+            .into_iter()
+            .for_each(|data| bytes.extend_from_slice(data));
+        Ok(bytes)
+    }
+
+    /// Decode from the snapshot file format
+    ///
+    /// # Examples
+    /// ```
+    /// use ultimate64::snapshot::MachineSnapshot;
+    /// assert!(MachineSnapshot::from_bytes(b"RU64SNAP\x02\x00\x00").is_err());
+    /// assert!(MachineSnapshot::from_bytes(b"PK\x03\x04").is_err());
+    /// ```
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < HEADER_SIZE || !bytes.starts_with(MAGIC) {
+            return Err(Error::decode("not a snapshot file"));
+        }
+        // Version 1 has no flags and always holds the SID registers
+        let (header, flags) = match u16::from_le_bytes([bytes[8], bytes[9]]) {
+            1 => (HEADER_SIZE - 1, FLAG_SID),
+            FORMAT_VERSION => (HEADER_SIZE, bytes[10]),
+            version => {
+                return Err(Error::decode(format!(
+                    "unsupported snapshot format version {version}"
+                )))
+            }
+        };
+        let expected = header + SECTIONS.iter().map(|(_, length)| length).sum::<usize>();
+        if bytes.len() != expected {
+            return Err(Error::decode(format!(
+                "snapshot has {} bytes, expected {expected}",
+                bytes.len()
+            )));
+        }
+        let mut rest = &bytes[header..];
+        let mut next = |length: usize| {
+            let (section, tail) = rest.split_at(length);
+            rest = tail;
+            section.to_vec()
+        };
+        Ok(Self {
+            ram: next(SECTIONS[0].1),
+            color_ram: next(SECTIONS[1].1),
+            vic: next(SECTIONS[2].1),
+            sid: Some(next(SECTIONS[3].1)).filter(|_| flags & FLAG_SID != 0),
+            cia1: next(SECTIONS[4].1),
+            cia2: next(SECTIONS[5].1),
+        })
+    }
+
+    /// Write to file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        Ok(std::fs::write(path, self.to_bytes()?)?)
+    }
+
+    /// Read from file; VICE snapshots (`.vsf`) are recognised and converted
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let bytes = std::fs::read(path)?;
+        if bytes.starts_with(vsf::MAGIC) {
+            return VsfFile::from_bytes(&bytes)?.to_snapshot();
+        }
+        Self::from_bytes(&bytes)
+    }
+
+    /// Write to file as VICE snapshot (`.vsf`)
+    pub fn save_vsf<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.check()?;
+        Ok(std::fs::write(
+            path,
+            VsfFile::from_snapshot(self).to_bytes(),
+        )?)
+    }
+}
+
+/// Register reads of a chip at `base`: address and length
+fn register_reads(base: u16, reads: &[(u16, u16)]) -> impl Iterator<Item = (u16, u16)> + '_ {
+    reads
+        .iter()
+        .map(move |&(offset, length)| (base + offset, length))
+}
+
+/// Registers of a chip from the results of its `reads`; skipped registers are zero
+fn registers_from_reads(
+    data: &mut impl Iterator<Item = Vec<u8>>,
+    reads: &[(u16, u16)],
+    size: usize,
+) -> Result<Vec<u8>> {
+    let mut registers = vec![0; size];
+    for &(offset, length) in reads {
+        let read = data.next().unwrap_or_default();
+        if read.len() != length as usize {
+            return Err(Error::decode("incomplete register read"));
+        }
+        let offset = offset as usize;
+        registers[offset..offset + read.len()].copy_from_slice(&read);
+    }
+    Ok(registers)
+}
+
+/// Writes restoring CIA registers, skipping the interrupt control register
+///
+/// Control register B is written before the time of day with the alarm bit
+/// clear, so the clock is set, and the time of day from hours to tenths, so
+/// the clock runs again.
+fn cia_writes(base: u16, registers: &[u8]) -> Vec<(u16, Vec<u8>)> {
+    let register = |offset: u16| registers[offset as usize];
+    let crb = register(CIA_CRB);
+    let mut writes = vec![
+        (base, registers[..8].to_vec()),
+        (base + 0x0e, vec![register(0x0e)]),
+        (base + CIA_CRB, vec![crb & !CIA_CRB_ALARM]),
+    ];
+    for offset in (CIA_TOD_TENTHS..=CIA_TOD_HOURS).rev() {
+        writes.push((base + offset, vec![register(offset)]));
+    }
+    writes.push((base + 0x0c, vec![register(0x0c)]));
+    if crb & CIA_CRB_ALARM != 0 {
+        writes.push((base + CIA_CRB, vec![crb]));
+    }
+    writes
+}
+
+/// Directory of named snapshot slots: `snapshots` next to the settings file
+pub fn slot_dir() -> Option<PathBuf> {
+    Settings::path()?.parent().map(|dir| dir.join("snapshots"))
+}
+
+/// File of the named slot; names may contain letters, digits, `-` and `_`
+///
+/// # Examples
+/// ```
+/// use ultimate64::snapshot::slot_path;
+/// assert!(slot_path("level-3").unwrap().ends_with("snapshots/level-3.snap"));
+/// assert!(slot_path("../level3").is_err());
+/// ```
+pub fn slot_path(name: &str) -> Result<PathBuf> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(Error::invalid(format!(
+            "invalid snapshot slot name: {name}"
+        )));
+    }
+    let dir = slot_dir()
+        .ok_or_else(|| Error::NotReady("no configuration directory for snapshot slots".into()))?;
+    Ok(dir.join(name).with_extension(SLOT_EXTENSION))
+}
+
+/// Names of all saved slots, sorted
+pub fn slots() -> Result<Vec<String>> {
+    let Some(dir) = slot_dir().filter(|dir| dir.is_dir()) else {
+        return Ok(Vec::new());
+    };
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == SLOT_EXTENSION) {
+            names.extend(
+                path.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned()),
+            );
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+
+    #[test]
+    fn test_capture_restore() {
+        let emulator = Emulator::default();
+        emulator.write_mem(0x0400, b"HELLO").unwrap();
+        emulator.write_mem(0xd020, &[0x06, 0x0e]).unwrap();
+        emulator.write_mem(0xdc0d, &[0x81]).unwrap();
+        let snapshot = MachineSnapshot::capture(&emulator).unwrap();
+        assert_eq!(snapshot.ram[0x0400..0x0405], *b"HELLO");
+        assert_eq!(snapshot.ram[0xd020], 0);
+        assert_eq!(snapshot.vic[0x20..0x22], [0x06, 0x0e]);
+        assert_eq!(snapshot.cia1[0x0d], 0);
+        assert_eq!(snapshot.vic[0x1e..0x20], [0, 0]);
+        // registers cleared by reading are skipped
+        for register in [0xd01e, 0xd01f, 0xdc0d, 0xdd0d] {
+            assert!(!MachineSnapshot::reads().any(|(address, length)| {
+                (address as u32..address as u32 + length as u32).contains(&register)
+            }));
+        }
+
+        let bytes = snapshot.to_bytes().unwrap();
+        assert_eq!(bytes.len(), 66675);
+        assert_eq!(MachineSnapshot::from_bytes(&bytes).unwrap(), snapshot);
+        let mut version1 = bytes.clone();
+        version1.splice(8..11, [1, 0]);
+        assert_eq!(MachineSnapshot::from_bytes(&version1).unwrap(), snapshot);
+
+        let without_sid = MachineSnapshot {
+            sid: None,
+            ..snapshot.clone()
+        };
+        let bytes = without_sid.to_bytes().unwrap();
+        assert_eq!(bytes[10], 0);
+        assert_eq!(MachineSnapshot::from_bytes(&bytes).unwrap(), without_sid);
+        assert!(!without_sid
+            .writes()
+            .unwrap()
+            .iter()
+            .any(|(address, _)| *address == 0xd400));
+
+        let other = Emulator::default();
+        snapshot.restore(&other).unwrap();
+        assert_eq!(other.read_mem(0x0400, 5).unwrap(), b"HELLO");
+        assert_eq!(other.read_mem(0xd020, 2).unwrap(), [0x06, 0x0e]);
+        assert_eq!(other.read_mem(0xdc0d, 1).unwrap(), [0x00]);
+    }
+
+    #[test]
+    fn test_writes() {
+        let emulator = Emulator::default();
+        let mut snapshot = MachineSnapshot::capture(&emulator).unwrap();
+        snapshot.ram[0xa004..0xa00c].copy_from_slice(b"CBMBASIC");
+        snapshot.cia1[0x0f] = 0x80;
+        let writes = snapshot.writes().unwrap();
+        let addresses: Vec<_> = writes.iter().map(|(address, _)| *address).collect();
+        // BASIC ROM is skipped, KERNAL area holds RAM
+        assert_eq!(addresses[..3], [0x0000, 0xc000, 0xe000]);
+        assert_eq!(writes[1].1.len(), 0x1000);
+        assert!(!addresses.contains(&0xdc0d));
+        let cia1: Vec<_> = writes
+            .iter()
+            .filter(|(address, _)| (0xdc00..0xdd00).contains(address))
+            .map(|(address, data)| (*address, data[0]))
+            .collect();
+        assert_eq!(
+            cia1[1..],
+            [
+                (0xdc0e, 0x00),
+                (0xdc0f, 0x00),
+                (0xdc0b, 0x00),
+                (0xdc0a, 0x00),
+                (0xdc09, 0x00),
+                (0xdc08, 0x00),
+                (0xdc0c, 0x00),
+                (0xdc0f, 0x80),
+            ]
+        );
+    }
+}
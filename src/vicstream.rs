@@ -1,6 +1,6 @@
 //! # VIC stream capturing
 
-use anyhow::{anyhow, bail, Ok, Result};
+use crate::{Error, Result};
 use byteorder::{ByteOrder, LittleEndian};
 use image::DynamicImage;
 use image::{imageops::FilterType, ImageBuffer, Rgb};
@@ -50,7 +50,7 @@ pub fn take_snapshot(url: &Url, image_file: Option<&Path>, scale: Option<u32>) -
 
     if let Some(path) = image_file {
         img.save(path)
-            .map_err(|e| anyhow!("Failed to save image: {}", e))?;
+            .map_err(|e| std::io::Error::other(format!("Failed to save image: {e}")))?;
     } else {
         // Print image to console on supported terminal
         let conf = viuer::Config {
@@ -58,7 +58,7 @@ pub fn take_snapshot(url: &Url, image_file: Option<&Path>, scale: Option<u32>) -
             ..Default::default()
         };
         let img = DynamicImage::ImageRgb8(img);
-        viuer::print(&img, &conf)?;
+        viuer::print(&img, &conf).map_err(std::io::Error::other)?;
     }
 
     Ok(())
@@ -67,9 +67,9 @@ pub fn take_snapshot(url: &Url, image_file: Option<&Path>, scale: Option<u32>) -
 pub fn get_socket(url: &Url) -> Result<UdpSocket> {
     let host = url
         .host_str()
-        .ok_or_else(|| anyhow!("Invalid URL host: {}", url))?;
+        .ok_or_else(|| Error::invalid(format!("Invalid URL host: {url}")))?;
     let port = url.port().unwrap_or(11000);
-    let multicast_group = Ipv4Addr::from_str(host)?;
+    let multicast_group = Ipv4Addr::from_str(host).map_err(Error::invalid)?;
     let listen_addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port);
 
     let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
@@ -88,7 +88,6 @@ pub fn get_socket(url: &Url) -> Result<UdpSocket> {
 
 /// Capture single VIC frame
 pub fn capture_frame(udp_socket: UdpSocket) -> Result<Vec<u8>> {
-    use std::result::Result::Ok;
     let mut frame: Vec<u8> = Vec::with_capacity(384 * 272 / 2);
     let mut buf = [0; 1024];
     loop {
@@ -99,14 +98,26 @@ pub fn capture_frame(udp_socket: UdpSocket) -> Result<Vec<u8>> {
                 }
             }
             Err(e) if e.kind() == ErrorKind::TimedOut => continue,
-            Err(e) => bail!("Failed to receive VIC data: {}", e),
+            Err(e) => {
+                return Err(std::io::Error::new(
+                    e.kind(),
+                    format!("Failed to receive VIC data: {e}"),
+                )
+                .into())
+            }
         }
     }
     loop {
         let (len, _addr) = match udp_socket.recv_from(&mut buf) {
             Ok(v) => v,
             Err(e) if e.kind() == ErrorKind::TimedOut => continue,
-            Err(e) => bail!("Failed to receive VIC data: {}", e),
+            Err(e) => {
+                return Err(std::io::Error::new(
+                    e.kind(),
+                    format!("Failed to receive VIC data: {e}"),
+                )
+                .into())
+            }
         };
 
         if len >= HEADER_LEN {
@@ -0,0 +1,555 @@
+//! # VICE snapshot files
+//!
+//! Reads and writes the `.vsf` snapshot format of the
+//! [VICE](https://vice-emu.sourceforge.io) emulator, so that a machine state
+//! can move between VICE and an Ultimate as a [`MachineSnapshot`].
+//!
+//! A VSF file starts with a header followed by a sequence of modules:
+//!
+//! | Size | Content                                              |
+//! |------|------------------------------------------------------|
+//! | 19   | Magic `VICE Snapshot File` followed by $1A           |
+//! | 2    | Format version, major and minor                      |
+//! | 16   | Machine name, zero padded, e.g. `C64`                |
+//! | 13   | Optional: magic `VICE Version` followed by $1A       |
+//! | 8    | Optional: VICE version (4 bytes) and revision (LE)   |
+//!
+//! Each module has a 22 byte header with a zero padded 16 byte name, major
+//! and minor version, and the module size including the header (LE).
+//!
+//! Only the modules holding memory and chip registers are read:
+//!
+//! - `C64MEM`: processor port data and direction, EXROM, GAME, then 64K RAM
+//! - `CIA1`, `CIA2`: the 16 registers, with the timers as current counter
+//!   values, followed by the two timer latches (LE words)
+//! - `VIC-II`: internal state including colour RAM at offset 43, and the
+//!   64 registers at offset 1119
+//!
+//! When writing, the `MAINCPU`, `SID` and `GLUE` modules that VICE requires
+//! are added, and every module is written with all fields of the version
+//! declared, as listed in [`MAINCPU_LAYOUT`], [`C64MEM_LAYOUT`],
+//! [`CIA_LAYOUT`], [`SID_LAYOUT`], [`VICII_LAYOUT`] and [`GLUE_LAYOUT`].
+//! Clocks are 64 bit as in VICE 3. Internal state not known from an Ultimate
+//! is zero; the video bank is derived from CIA 2 port A.
+//!
+//! The CPU registers cannot be read from an Ultimate, so the `MAINCPU`
+//! module holds a fixed state: the program counter at the BASIC warm start
+//! $E37B, which returns to the `READY.` prompt with memory intact, the stack
+//! pointer at $FF, and interrupts disabled. A snapshot of a running program
+//! therefore resumes in BASIC, from where the program can be restarted.
+
+use crate::{snapshot::MachineSnapshot, Error, Result};
+use log::warn;
+
+/// First bytes of a VICE snapshot file
+pub const MAGIC: &[u8; 19] = b"VICE Snapshot File\x1a";
+
+/// Marker of the optional VICE version block after the machine name
+const VERSION_MAGIC: &[u8; 13] = b"VICE Version\x1a";
+
+/// Length of machine and module names
+const NAME_LENGTH: usize = 16;
+
+/// Length of a module header: name, major and minor version, and size
+const MODULE_HEADER_SIZE: usize = NAME_LENGTH + 1 + 1 + 4;
+
+/// Snapshot format version written
+const FORMAT_VERSION: (u8, u8) = (2, 0);
+
+/// VICE version recorded in files written
+const VICE_VERSION: [u8; 4] = [3, 7, 0, 0];
+
+/// Size of the C64 RAM
+const RAM_SIZE: usize = 0x10000;
+
+/// Offset of the RAM in the `C64MEM` module
+const MEM_RAM_OFFSET: usize = 4;
+
+/// Offset of the timer latches in the `CIA1` and `CIA2` modules
+const CIA_LATCH_OFFSET: usize = 16;
+
+/// Offset of the colour RAM in the `VIC-II` module
+const VICII_COLOR_RAM_OFFSET: usize = 43;
+
+/// Offset of the registers in the `VIC-II` module
+const VICII_REGISTER_OFFSET: usize = 1119;
+
+/// Field of a module layout: name and size in bytes
+pub type Field = (&'static str, usize);
+
+/// `MAINCPU` module fields, version 1.1
+pub const MAINCPU_LAYOUT: &[Field] = &[
+    ("clk", 8),
+    ("a", 1),
+    ("x", 1),
+    ("y", 1),
+    ("sp", 1),
+    ("pc", 2),
+    ("status", 1),
+    ("last_opcode_info", 4),
+    ("irq_clk", 8),
+    ("nmi_clk", 8),
+    ("num_last_stolen_cycles", 4),
+    ("last_stolen_cycles_clk", 8),
+    ("nirq", 4),
+    ("nnmi", 4),
+    ("global_pending_int", 4),
+];
+
+/// `C64MEM` module fields, version 0.0
+pub const C64MEM_LAYOUT: &[Field] = &[
+    ("pport_data", 1),
+    ("pport_dir", 1),
+    ("exrom", 1),
+    ("game", 1),
+    ("ram", RAM_SIZE),
+    ("pport_data_out", 1),
+    ("pport_data_read", 1),
+    ("pport_dir_read", 1),
+    ("pport_data_set_clk_bit6", 8),
+    ("pport_data_set_clk_bit7", 8),
+    ("pport_data_set_bit6", 1),
+    ("pport_data_set_bit7", 1),
+    ("pport_data_falloff_bit6", 1),
+    ("pport_data_falloff_bit7", 1),
+];
+
+/// `CIA1` and `CIA2` module fields, version 2.2
+pub const CIA_LAYOUT: &[Field] = &[
+    ("registers", 16),
+    ("latches", 4),
+    ("icr", 1),
+    ("timer_flags", 1),
+    ("sr_bits", 1),
+    ("tod_alarm", 4),
+    ("rdi", 1),
+    ("tod_flags", 1),
+    ("tod_latch", 4),
+    ("tod_clk", 8),
+    ("timer_a", 6),
+    ("timer_b", 6),
+    ("shifter", 1),
+    ("sdr_valid", 1),
+    ("tod_ticks", 4),
+];
+
+/// `SID` module fields, version 1.1
+pub const SID_LAYOUT: &[Field] = &[("engine", 1), ("model", 1), ("registers", 32)];
+
+/// `VIC-II` module fields, version 1.1
+pub const VICII_LAYOUT: &[Field] = &[
+    ("allow_bad_lines", 1),
+    ("bad_line", 1),
+    ("blank", 1),
+    ("color_buf", 40),
+    ("color_ram", 0x400),
+    ("idle_state", 1),
+    ("light_pen_trigger_cycle", 1),
+    ("light_pen_x", 1),
+    ("light_pen_y", 1),
+    ("matrix_buf", 40),
+    ("new_sprite_dma_mask", 1),
+    ("ram_base", 4),
+    ("raster_cycle", 1),
+    ("raster_line", 2),
+    ("registers", 0x40),
+    ("sprite_background_collisions", 1),
+    ("sprite_dma_mask", 1),
+    ("sprite_sprite_collisions", 1),
+    ("video_bank", 2),
+    ("vc", 2),
+    ("vc_add", 1),
+    ("vc_base", 2),
+    ("video_interrupt", 1),
+    // memory pointer, pointer increment and expansion flip flop of 8 sprites
+    ("sprites", 8 * 3),
+    ("fetch_event_tick", 4),
+    ("fetch_event_type", 1),
+];
+
+/// `GLUE` module fields, version 1.0
+pub const GLUE_LAYOUT: &[Field] = &[
+    ("type", 1),
+    ("video_bank", 1),
+    ("alarm_active", 1),
+    ("alarm_clk", 8),
+];
+
+/// BASIC warm start, where the `MAINCPU` module written resumes
+const BASIC_WARM_START: u16 = 0xe37b;
+
+/// Processor status written: interrupts disabled and the unused bit set
+const CPU_STATUS: u8 = 0x24;
+
+/// Single module of a VICE snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Module {
+    /// Module name, e.g. `C64MEM`
+    pub name: String,
+    /// Major version of the module layout
+    pub major: u8,
+    /// Minor version of the module layout
+    pub minor: u8,
+    /// Module content following the header
+    pub data: Vec<u8>,
+}
+
+/// VICE snapshot file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VsfFile {
+    /// Machine name, e.g. `C64` or `C64SC`
+    pub machine: String,
+    /// Snapshot format version: major and minor
+    pub version: (u8, u8),
+    /// VICE version and revision, if recorded
+    pub vice_version: Option<([u8; 4], u32)>,
+    /// Modules in file order
+    pub modules: Vec<Module>,
+}
+
+/// Zero padded name field as string
+fn read_name(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+/// Append `name` as zero padded name field
+fn write_name(bytes: &mut Vec<u8>, name: &str) {
+    let mut field = [0; NAME_LENGTH];
+    let length = name.len().min(NAME_LENGTH);
+    field[..length].copy_from_slice(&name.as_bytes()[..length]);
+    bytes.extend_from_slice(&field);
+}
+
+fn truncated() -> Error {
+    Error::decode("truncated VICE snapshot")
+}
+
+/// Module content in `layout` with `values` for the named fields, zero elsewhere
+fn layout_data(layout: &[Field], values: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(layout.iter().map(|(_, size)| size).sum());
+    for &(name, size) in layout {
+        match values.iter().find(|(field, _)| *field == name) {
+            Some((_, value)) => {
+                debug_assert_eq!(value.len(), size, "size of {name}");
+                data.extend_from_slice(value);
+            }
+            None => data.resize(data.len() + size, 0),
+        }
+    }
+    data
+}
+
+impl VsfFile {
+    /// Module with the given name, if present
+    pub fn module(&self, name: &str) -> Option<&Module> {
+        self.modules.iter().find(|module| module.name == name)
+    }
+
+    /// Decode from file content
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if !bytes.starts_with(MAGIC) {
+            return Err(Error::decode("not a VICE snapshot file"));
+        }
+        let mut offset = MAGIC.len();
+        let header = bytes
+            .get(offset..offset + 2 + NAME_LENGTH)
+            .ok_or_else(truncated)?;
+        let version = (header[0], header[1]);
+        let machine = read_name(&header[2..]);
+        offset += header.len();
+
+        let mut vice_version = None;
+        if bytes[offset..].starts_with(VERSION_MAGIC) {
+            offset += VERSION_MAGIC.len();
+            let block = bytes.get(offset..offset + 8).ok_or_else(truncated)?;
+            let revision = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+            vice_version = Some(([block[0], block[1], block[2], block[3]], revision));
+            offset += block.len();
+        }
+
+        let mut modules = Vec::new();
+        while offset < bytes.len() {
+            let header = bytes
+                .get(offset..offset + MODULE_HEADER_SIZE)
+                .ok_or_else(truncated)?;
+            let size = u32::from_le_bytes([header[18], header[19], header[20], header[21]]);
+            let data = (size as usize)
+                .checked_sub(MODULE_HEADER_SIZE)
+                .and_then(|length| bytes.get(offset + MODULE_HEADER_SIZE..)?.get(..length))
+                .ok_or_else(truncated)?;
+            modules.push(Module {
+                name: read_name(&header[..NAME_LENGTH]),
+                major: header[16],
+                minor: header[17],
+                data: data.to_vec(),
+            });
+            offset += size as usize;
+        }
+        Ok(Self {
+            machine,
+            version,
+            vice_version,
+            modules,
+        })
+    }
+
+    /// Encode as file content
+    ///
+    /// # Examples
+    /// ```
+    /// use ultimate64::vsf::{Module, VsfFile};
+    /// let file = VsfFile {
+    ///     machine: "C64".to_string(),
+    ///     version: (2, 0),
+    ///     vice_version: None,
+    ///     modules: vec![Module { name: "TEST".to_string(), major: 1, minor: 0, data: vec![42] }],
+    /// };
+    /// let bytes = file.to_bytes();
+    /// assert_eq!(bytes.len(), 19 + 2 + 16 + 22 + 1);
+    /// assert_eq!(VsfFile::from_bytes(&bytes).unwrap(), file);
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[self.version.0, self.version.1]);
+        write_name(&mut bytes, &self.machine);
+        if let Some((version, revision)) = self.vice_version {
+            bytes.extend_from_slice(VERSION_MAGIC);
+            bytes.extend_from_slice(&version);
+            bytes.extend_from_slice(&revision.to_le_bytes());
+        }
+        for module in &self.modules {
+            write_name(&mut bytes, &module.name);
+            bytes.extend_from_slice(&[module.major, module.minor]);
+            let size = (MODULE_HEADER_SIZE + module.data.len()) as u32;
+            bytes.extend_from_slice(&size.to_le_bytes());
+            bytes.extend_from_slice(&module.data);
+        }
+        bytes
+    }
+
+    /// Machine state from the memory, CIA and VIC-II modules
+    ///
+    /// Fails without a `C64MEM` module; missing or unrecognised chip modules
+    /// leave the corresponding registers zero. The SID module is not read, so
+    /// the SID registers are marked absent and left alone on restore.
+    pub fn to_snapshot(&self) -> Result<MachineSnapshot> {
+        let memory = self
+            .module("C64MEM")
+            .ok_or_else(|| Error::decode("VICE snapshot has no C64MEM module"))?;
+        let mut ram = memory
+            .data
+            .get(MEM_RAM_OFFSET..MEM_RAM_OFFSET + RAM_SIZE)
+            .ok_or_else(truncated)?
+            .to_vec();
+        ram[0] = memory.data[1]; // processor port direction
+        ram[1] = memory.data[0]; // processor port data
+        ram[0xd000..0xe000].fill(0);
+
+        let cia = |name: &str| match self.module(name).map(|module| &module.data) {
+            Some(data) if data.len() >= CIA_LATCH_OFFSET + 4 => {
+                let mut registers = data[..16].to_vec();
+                // restore timer latches rather than current counter values
+                registers[4..8].copy_from_slice(&data[CIA_LATCH_OFFSET..CIA_LATCH_OFFSET + 4]);
+                registers
+            }
+            _ => {
+                warn!("No usable {name} module in VICE snapshot");
+                vec![0; 16]
+            }
+        };
+        let (cia1, cia2) = (cia("CIA1"), cia("CIA2"));
+
+        let vicii = self.module("VIC-II").map(|module| &module.data);
+        let (color_ram, vic) = match vicii {
+            Some(data) if data.len() >= VICII_REGISTER_OFFSET + 0x2f => (
+                data[VICII_COLOR_RAM_OFFSET..VICII_COLOR_RAM_OFFSET + 0x400].to_vec(),
+                data[VICII_REGISTER_OFFSET..VICII_REGISTER_OFFSET + 0x2f].to_vec(),
+            ),
+            _ => {
+                warn!("No usable VIC-II module in VICE snapshot");
+                (vec![0; 0x400], vec![0; 0x2f])
+            }
+        };
+
+        Ok(MachineSnapshot {
+            ram,
+            color_ram,
+            vic,
+            sid: None,
+            cia1,
+            cia2,
+        })
+    }
+
+    /// VICE snapshot with CPU, memory, CIA, SID, VIC-II and glue logic modules for `snapshot`
+    ///
+    /// The CPU state is fixed; see the [module documentation](self). Absent
+    /// SID registers are written as zero.
+    pub fn from_snapshot(snapshot: &MachineSnapshot) -> Self {
+        let maincpu = layout_data(
+            MAINCPU_LAYOUT,
+            &[
+                ("sp", &[0xff]),
+                ("pc", &BASIC_WARM_START.to_le_bytes()),
+                ("status", &[CPU_STATUS]),
+            ],
+        );
+
+        let (data, dir) = (snapshot.ram[1], snapshot.ram[0]);
+        let memory = layout_data(
+            C64MEM_LAYOUT,
+            &[
+                ("pport_data", &[data]),
+                ("pport_dir", &[dir]),
+                ("exrom", &[1]),
+                ("game", &[1]),
+                ("ram", &snapshot.ram),
+                ("pport_data_out", &[data]),
+                ("pport_data_read", &[data]),
+                ("pport_dir_read", &[dir]),
+            ],
+        );
+
+        let cia = |registers: &[u8]| {
+            layout_data(
+                CIA_LAYOUT,
+                &[
+                    ("registers", registers),
+                    ("latches", &registers[4..8]),
+                    ("tod_latch", &registers[8..12]),
+                ],
+            )
+        };
+
+        let mut sid_registers = snapshot.sid.clone().unwrap_or_default();
+        sid_registers.resize(32, 0);
+        let sid = layout_data(SID_LAYOUT, &[("registers", &sid_registers)]);
+
+        // video bank selected by the inverted, output enabled port A bits of CIA 2
+        let video_bank = !(snapshot.cia2[0] | !snapshot.cia2[2]) & 0x03;
+        let mut vic_registers = snapshot.vic.clone();
+        vic_registers.resize(0x40, 0);
+        let vicii = layout_data(
+            VICII_LAYOUT,
+            &[
+                ("color_ram", &snapshot.color_ram),
+                ("registers", &vic_registers),
+                ("video_bank", &(video_bank as u16 * 0x4000).to_le_bytes()),
+            ],
+        );
+        let glue = layout_data(GLUE_LAYOUT, &[("video_bank", &[video_bank])]);
+
+        let module = |name: &str, (major, minor), data| Module {
+            name: name.to_string(),
+            major,
+            minor,
+            data,
+        };
+        Self {
+            machine: "C64".to_string(),
+            version: FORMAT_VERSION,
+            vice_version: Some((VICE_VERSION, 0)),
+            modules: vec![
+                module("MAINCPU", (1, 1), maincpu),
+                module("C64MEM", (0, 0), memory),
+                module("CIA1", (2, 2), cia(&snapshot.cia1)),
+                module("CIA2", (2, 2), cia(&snapshot.cia2)),
+                module("SID", (1, 1), sid),
+                module("VIC-II", (1, 1), vicii),
+                module("GLUE", (1, 0), glue),
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let mut ram = vec![0; RAM_SIZE];
+        ram[..2].copy_from_slice(&[0x2f, 0x37]);
+        ram[0x0400..0x0403].copy_from_slice(b"VSF");
+        let cia1: Vec<u8> = (0..16).collect();
+        let snapshot = MachineSnapshot {
+            ram,
+            color_ram: vec![0x0e; 0x400],
+            vic: (0..0x2f).collect(),
+            sid: None,
+            cia1: cia1.clone(),
+            cia2: vec![0xff; 16],
+        };
+        let bytes = VsfFile::from_snapshot(&snapshot).to_bytes();
+        let file = VsfFile::from_bytes(&bytes).unwrap();
+        assert_eq!(file.machine, "C64");
+        assert_eq!(file.vice_version, Some((VICE_VERSION, 0)));
+        assert_eq!(file.module("C64MEM").unwrap().data[..2], [0x37, 0x2f]);
+        assert_eq!(file.module("CIA1").unwrap().data[16..20], cia1[4..8]);
+        assert_eq!(file.to_snapshot().unwrap(), snapshot);
+    }
+
+    #[test]
+    fn test_module_sizes() {
+        let snapshot = MachineSnapshot {
+            ram: vec![0; RAM_SIZE],
+            color_ram: vec![0; 0x400],
+            vic: vec![0; 0x2f],
+            sid: None,
+            cia1: vec![0; 16],
+            cia2: [0x95, 0, 0x3f].into_iter().chain([0; 13]).collect(),
+        };
+        let file = VsfFile::from_snapshot(&snapshot);
+        let size = |layout: &[Field]| layout.iter().map(|(_, size)| size).sum::<usize>();
+        // sizes of the VICE 3 modules, without the module header
+        for (name, layout, expected) in [
+            ("MAINCPU", MAINCPU_LAYOUT, 59),
+            ("C64MEM", C64MEM_LAYOUT, 65563),
+            ("CIA1", CIA_LAYOUT, 59),
+            ("CIA2", CIA_LAYOUT, 59),
+            ("SID", SID_LAYOUT, 34),
+            ("VIC-II", VICII_LAYOUT, 1223),
+            ("GLUE", GLUE_LAYOUT, 11),
+        ] {
+            assert_eq!(size(layout), expected, "{name} layout");
+            assert_eq!(file.module(name).unwrap().data.len(), expected, "{name}");
+        }
+        let maincpu = &file.module("MAINCPU").unwrap().data;
+        assert_eq!(maincpu[12..15], [0x7b, 0xe3, CPU_STATUS]);
+        assert_eq!(size(&C64MEM_LAYOUT[..4]), MEM_RAM_OFFSET);
+        assert_eq!(size(&CIA_LAYOUT[..1]), CIA_LATCH_OFFSET);
+        assert_eq!(size(&VICII_LAYOUT[..4]), VICII_COLOR_RAM_OFFSET);
+        assert_eq!(size(&VICII_LAYOUT[..14]), VICII_REGISTER_OFFSET);
+        // port A bits %01 select the video bank at $8000
+        let bank = size(&VICII_LAYOUT[..18]);
+        assert_eq!(
+            file.module("VIC-II").unwrap().data[bank..bank + 2],
+            [0x00, 0x80]
+        );
+        assert_eq!(file.module("GLUE").unwrap().data[1], 2);
+        let names: Vec<_> = file.modules.iter().map(|module| &module.name).collect();
+        assert_eq!(
+            names,
+            ["MAINCPU", "C64MEM", "CIA1", "CIA2", "SID", "VIC-II", "GLUE"]
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(VsfFile::from_bytes(b"RU64SNAP").is_err());
+        let mut bytes = VsfFile {
+            machine: "C64".to_string(),
+            version: FORMAT_VERSION,
+            vice_version: None,
+            modules: vec![],
+        }
+        .to_bytes();
+        assert!(matches!(
+            VsfFile::from_bytes(&bytes).unwrap().to_snapshot(),
+            Err(Error::Decode(_))
+        ));
+        bytes.extend_from_slice(b"C64MEM");
+        assert!(VsfFile::from_bytes(&bytes).is_err());
+    }
+}
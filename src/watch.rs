@@ -0,0 +1,218 @@
+//! # Memory watching
+//!
+//! A [`Watcher`] polls one or more memory regions and reports the bytes that
+//! changed since the previous poll, which helps following e.g. a game's
+//! state without repeated dumps.
+//!
+//! # Examples
+//! ~~~ rust, ignore
+//! use ultimate64::watch::{Region, Watcher};
+//! let mut watcher = Watcher::new(vec!["border=0xd020:2".parse()?])?;
+//! loop {
+//!     for change in watcher.poll(&ultimate)? {
+//!         println!("{change}");
+//!     }
+//!     std::thread::sleep(std::time::Duration::from_millis(50));
+//! }
+//! ~~~
+
+use crate::{auxiliary::check_address_overflow, Error, Result, Ultimate};
+use core::fmt::Display;
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+/// Memory region to watch, optionally named
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    /// Name used when reporting changes
+    pub name: Option<String>,
+    /// Start address
+    pub address: u16,
+    /// Number of bytes
+    pub length: u16,
+}
+
+impl Region {
+    /// Parse `[NAME=]ADDRESS[:LENGTH]`, using `default_length` if no length is given
+    ///
+    /// # Examples
+    /// ```
+    /// use ultimate64::watch::Region;
+    /// let region = Region::parse("border=0xd020:2", 1).unwrap();
+    /// assert_eq!(region.name.as_deref(), Some("border"));
+    /// assert_eq!((region.address, region.length), (0xd020, 2));
+    /// assert_eq!(Region::parse("1024", 16).unwrap().length, 16);
+    /// assert!(Region::parse("0xffff:2", 1).is_err());
+    /// ```
+    pub fn parse(s: &str, default_length: u16) -> Result<Self> {
+        let (name, range) = match s.split_once('=') {
+            Some((name, range)) => (Some(name.trim().to_string()), range),
+            None => (None, s),
+        };
+        let number = |s: &str| {
+            parse_int::parse::<u16>(s.trim())
+                .map_err(|_| Error::invalid(format!("invalid number in memory region {s}")))
+        };
+        let (address, length) = match range.split_once(':') {
+            Some((address, length)) => (number(address)?, number(length)?),
+            None => (number(range)?, default_length),
+        };
+        if length == 0 {
+            return Err(Error::invalid(format!("empty memory region: {s}")));
+        }
+        check_address_overflow(address, length)?;
+        Ok(Self {
+            name,
+            address,
+            length,
+        })
+    }
+
+    /// Name of the byte at `address` in the region, e.g. `border+1`
+    fn label(&self, address: u16) -> Option<String> {
+        self.name.as_ref().map(|name| match address - self.address {
+            0 => name.clone(),
+            offset => format!("{name}+{offset}"),
+        })
+    }
+}
+
+impl FromStr for Region {
+    type Err = Error;
+
+    /// Parse `[NAME=]ADDRESS[:LENGTH]`; the length defaults to one byte
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s, 1)
+    }
+}
+
+/// Change of a single byte
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    /// Time since the watcher was created
+    pub elapsed: Duration,
+    /// Address of the byte
+    pub address: u16,
+    /// Name of the byte, if its region is named
+    pub label: Option<String>,
+    /// Previous value; `None` on the first poll
+    pub old: Option<u8>,
+    /// Current value
+    pub new: u8,
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:10.3}s {:#06x}",
+            self.elapsed.as_secs_f64(),
+            self.address
+        )?;
+        if let Some(label) = &self.label {
+            write!(f, " {label}")?;
+        }
+        match self.old {
+            Some(old) => write!(f, ": {old:#04x} -> {:#04x}", self.new),
+            None => write!(f, ": {:#04x}", self.new),
+        }
+    }
+}
+
+/// Polls memory regions and reports changed bytes
+pub struct Watcher {
+    regions: Vec<Region>,
+    previous: Vec<Vec<u8>>,
+    start: Instant,
+}
+
+impl Watcher {
+    /// New watcher; the clock for [`Change::elapsed`] starts now
+    pub fn new(regions: Vec<Region>) -> Result<Self> {
+        if regions.is_empty() {
+            return Err(Error::invalid("no memory region to watch"));
+        }
+        Ok(Self {
+            regions,
+            previous: Vec::new(),
+            start: Instant::now(),
+        })
+    }
+
+    /// Regions being watched
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// Time since the watcher was created
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Read all regions and return bytes that differ from the previous poll
+    ///
+    /// The first poll reports every byte with no previous value.
+    pub fn poll<U: Ultimate + ?Sized>(&mut self, ultimate: &U) -> Result<Vec<Change>> {
+        let current = self
+            .regions
+            .iter()
+            .map(|region| ultimate.read_mem(region.address, region.length))
+            .collect::<Result<Vec<_>>>()?;
+        let elapsed = self.start.elapsed();
+        let mut changes = Vec::new();
+        for (index, (region, data)) in self.regions.iter().zip(&current).enumerate() {
+            let previous = self.previous.get(index);
+            for (offset, &new) in data.iter().enumerate() {
+                let old = previous.map(|previous| previous[offset]);
+                if old == Some(new) {
+                    continue;
+                }
+                let address = region.address + offset as u16;
+                changes.push(Change {
+                    elapsed,
+                    address,
+                    label: region.label(address),
+                    old,
+                    new,
+                });
+            }
+        }
+        self.previous = current;
+        Ok(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+
+    #[test]
+    fn test_poll() {
+        let emulator = Emulator::default();
+        let mut watcher = Watcher::new(vec![
+            "border=0xd020:2".parse().unwrap(),
+            Region::parse("0x0400", 4).unwrap(),
+        ])
+        .unwrap();
+        let initial = watcher.poll(&emulator).unwrap();
+        assert_eq!(initial.len(), 6);
+        assert!(initial.iter().all(|change| change.old.is_none()));
+        assert!(watcher.poll(&emulator).unwrap().is_empty());
+
+        emulator.write_mem(0xd021, &[0x06]).unwrap();
+        emulator.write_mem(0x0402, &[0x01]).unwrap();
+        let changes = watcher.poll(&emulator).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].address, 0xd021);
+        assert_eq!(changes[0].label.as_deref(), Some("border+1"));
+        assert_eq!((changes[0].old, changes[0].new), (Some(0x00), 0x06));
+        assert_eq!(changes[1].address, 0x0402);
+        assert_eq!(changes[1].label, None);
+        assert!(changes[0]
+            .to_string()
+            .ends_with("0xd021 border+1: 0x00 -> 0x06"));
+    }
+}
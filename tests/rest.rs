@@ -0,0 +1,369 @@
+//! End-to-end tests of the blocking client against the mock device
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{IpAddr, Ipv4Addr, TcpListener, TcpStream},
+    time::{Duration, Instant},
+};
+use ultimate64::{
+    capabilities::{Feature, Product},
+    config::ConfigValue,
+    discovery::Scan,
+    drives::{DriveId, MountMode},
+    mock::MockServer,
+    Error, Rest, RestBuilder, RestGroup, Ultimate,
+};
+use url::Host;
+
+const PASSWORD: &str = "secret";
+
+fn connect() -> (MockServer, Rest) {
+    let server = MockServer::start(Some(PASSWORD)).unwrap();
+    let ultimate = Rest::with_port(&server.host(), server.port(), Some(PASSWORD.into())).unwrap();
+    (server, ultimate)
+}
+
+#[test]
+fn test_info() {
+    let (server, ultimate) = connect();
+    let info = ultimate.info().unwrap();
+    assert_eq!(info, server.state().info);
+    assert!(ultimate.version().unwrap().contains("\"version\":\"0.1\""));
+}
+
+#[test]
+fn test_builder() {
+    let (server, _) = connect();
+    let ultimate = RestBuilder::new(&server.host())
+        .port(server.port())
+        .password(Some(PASSWORD.into()))
+        .connect_timeout(Duration::from_secs(1))
+        .timeout(Duration::from_secs(5))
+        .retries(2)
+        .user_agent("integration-test")
+        .build()
+        .unwrap();
+    assert_eq!(ultimate.info().unwrap().hostname, "mock-u64");
+}
+
+#[test]
+fn test_retry() {
+    // port that was free a moment ago, so nothing is listening on it
+    let port = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let ultimate = RestBuilder::new(&Host::Ipv4(Ipv4Addr::LOCALHOST))
+        .port(port)
+        .retries(2)
+        .backoff(Duration::from_millis(50))
+        .build()
+        .unwrap();
+    let start = Instant::now();
+    assert!(matches!(ultimate.info(), Err(Error::Network(_))));
+    assert!(start.elapsed() >= Duration::from_millis(150));
+
+    // actions are sent once, as the device may have acted before a timeout
+    let start = Instant::now();
+    assert!(matches!(ultimate.reset(), Err(Error::Network(_))));
+    assert!(start.elapsed() < Duration::from_millis(50));
+    let start = Instant::now();
+    assert!(ultimate.write_mem(0x1000, &[1]).is_err());
+    assert!(start.elapsed() >= Duration::from_millis(150));
+}
+
+#[test]
+fn test_wrong_password() {
+    let (server, _) = connect();
+    let ultimate = Rest::with_port(&server.host(), server.port(), Some("wrong".into())).unwrap();
+    assert!(matches!(ultimate.info(), Err(Error::Auth)));
+    let ultimate = Rest::with_port(&server.host(), server.port(), None).unwrap();
+    assert!(matches!(ultimate.reset(), Err(Error::Auth)));
+}
+
+#[test]
+fn test_memory() {
+    let (server, ultimate) = connect();
+    ultimate.write_mem(0xc000, &[0xa9, 0x01, 0x60]).unwrap();
+    assert_eq!(ultimate.read_mem(0xc000, 3).unwrap(), [0xa9, 0x01, 0x60]);
+    assert_eq!(ultimate.read_le_word(0xc000).unwrap(), 0x01a9);
+    assert_eq!(server.state().ram[0xc002], 0x60);
+
+    let (address, len) = ultimate.load_data(&[0x01, 0x08, 1, 2], None).unwrap();
+    assert_eq!((address, len), (0x0801, 2));
+    ultimate.load_prg(&[0x00, 0x20, 0xea, 0xea]).unwrap();
+    assert_eq!(server.state().ram[0x2000..0x2002], [0xea, 0xea]);
+}
+
+#[test]
+fn test_chunked_memory() {
+    let server = MockServer::start(Some(PASSWORD)).unwrap();
+    let ultimate = RestBuilder::new(&server.host())
+        .port(server.port())
+        .password(Some(PASSWORD.into()))
+        .chunk_size(0x1000)
+        .connections(4)
+        .build()
+        .unwrap();
+    let data: Vec<u8> = (0..0xffffu32).map(|i| (i * 7 % 251) as u8).collect();
+    let mut progress = Vec::new();
+    ultimate
+        .write_mem_with_progress(0x0000, &data, |done, total| progress.push((done, total)))
+        .unwrap();
+    assert_eq!(progress.len(), 16);
+    assert!(progress.windows(2).all(|w| w[0].0 < w[1].0));
+    assert_eq!(progress.last(), Some(&(0xffff, 0xffff)));
+    assert_eq!(server.state().ram[..0xffff], data[..]);
+
+    assert_eq!(ultimate.read_mem(0x0000, 0xffff).unwrap(), data);
+    let requests = server.state().requests.clone();
+    let reads = requests
+        .iter()
+        .filter(|r| r.starts_with("GET machine:readmem"));
+    assert_eq!(reads.count(), 16);
+    assert!(ultimate.read_mem(0xfff0, 0x20).is_err());
+
+    // payloads beyond 64 KiB must not wrap around to zero page
+    let before = server.state().requests.len();
+    let oversized = vec![0xea; 0x10001];
+    assert!(matches!(
+        ultimate.write_mem(0x0000, &oversized),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert!(ultimate.write_mem(0x0801, &[0xea; 70000]).is_err());
+    assert_eq!(server.state().requests.len(), before);
+    assert_eq!(server.state().ram[..0xffff], data[..]);
+}
+
+#[test]
+fn test_type_text() {
+    let (server, ultimate) = connect();
+    ultimate.type_text("list\n").unwrap();
+    let state = server.state();
+    assert_eq!(state.ram[0xc6], 5);
+    assert_eq!(state.ram[0x277..0x27c], [0x4c, 0x49, 0x53, 0x54, 0x0d]);
+}
+
+#[test]
+fn test_machine() {
+    let (server, ultimate) = connect();
+    ultimate.pause().unwrap();
+    assert!(server.state().paused);
+    ultimate.resume().unwrap();
+    assert!(!server.state().paused);
+
+    ultimate.write_debugreg(0x2a).unwrap();
+    assert_eq!(ultimate.read_debugreg().unwrap(), 0x2a);
+    ultimate
+        .wait_for_debugreg(0x2a, std::time::Duration::from_secs(1))
+        .unwrap();
+    assert!(server
+        .state()
+        .requests
+        .contains(&"PUT machine:debugreg".to_string()));
+}
+
+#[test]
+fn test_drives() {
+    let (server, ultimate) = connect();
+    ultimate
+        .mount_remote_image(
+            "/Usb0/games/disk 1.d64",
+            "a".into(),
+            MountMode::ReadOnly,
+            false,
+        )
+        .unwrap();
+    let drive = ultimate.drive(DriveId::A).unwrap();
+    assert_eq!(drive.image_path.as_deref(), Some("/Usb0/games"));
+    assert_eq!(drive.image_file.as_deref(), Some("disk 1.d64"));
+
+    ultimate.drive_off(DriveId::B).unwrap();
+    assert!(!server.state().drives["b"].enabled);
+    ultimate.drive_eject(DriveId::A).unwrap();
+    assert_eq!(ultimate.drive(DriveId::A).unwrap().image_file, None);
+
+    let dir = std::env::temp_dir().join(format!("ru64-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let image = dir.join("game.d64");
+    std::fs::write(&image, [0; 256]).unwrap();
+    ultimate
+        .mount_disk_image(&image, "a".into(), MountMode::ReadWrite, false)
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    let drive = ultimate.drive(DriveId::A).unwrap();
+    assert_eq!(drive.image_file.as_deref(), Some("game.d64"));
+}
+
+#[test]
+fn test_config() {
+    let (server, ultimate) = connect();
+    let categories = ultimate.config_categories().unwrap();
+    assert!(categories.contains(&"Audio Mixer".to_string()));
+
+    let value = ConfigValue::from("+1 dB");
+    ultimate
+        .set_config_item("Audio Mixer", "Vol UltiSid 1", &value)
+        .unwrap();
+    let item = ultimate
+        .config_item("Audio Mixer", "Vol UltiSid 1")
+        .unwrap();
+    assert_eq!(item.current, value);
+
+    ultimate.save_config_to_flash().unwrap();
+    assert_eq!(server.state().flash["Audio Mixer"]["Vol UltiSid 1"], value);
+}
+
+/// Status code for a GET request sent verbatim, bypassing the client's encoding
+fn raw_get_status(server: &MockServer, path: &str) -> u16 {
+    let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, server.port())).unwrap();
+    write!(
+        stream,
+        "GET {path} HTTP/1.1\r\nHost: localhost\r\nX-password: {PASSWORD}\r\nConnection: close\r\n\r\n"
+    )
+    .unwrap();
+    let mut status = String::new();
+    BufReader::new(stream).read_line(&mut status).unwrap();
+    status.split_whitespace().nth(1).unwrap().parse().unwrap()
+}
+
+#[test]
+fn test_path_decoding() {
+    let (server, _) = connect();
+    assert_eq!(raw_get_status(&server, "/v1/configs/Audio%20Mixer"), 200);
+    assert_eq!(raw_get_status(&server, "/v1/configs/Audio+Mixer"), 404);
+    assert_eq!(raw_get_status(&server, "/v1/configs/%\u{20ac}"), 404);
+}
+
+#[test]
+fn test_device_error() {
+    let (_, ultimate) = connect();
+    match ultimate.config_category("No such category") {
+        Err(Error::Device { status, errors }) => {
+            assert_eq!(status, 404);
+            assert_eq!(errors, ["Category not found"]);
+        }
+        other => panic!("unexpected result: {other:?}"),
+    }
+}
+
+#[test]
+fn test_wait() {
+    let (server, ultimate) = connect();
+    let timeout = Duration::from_millis(600);
+    ultimate.wait_online(timeout).unwrap();
+    assert!(matches!(
+        ultimate.wait_ready(timeout),
+        Err(Error::Timeout(_))
+    ));
+
+    {
+        let mut state = server.state();
+        state.ram[0xcc] = 0; // cursor blinking
+        state.ram[0xd6] = 5; // cursor row
+        state.ram[0x0400 + 4 * 40..0x0400 + 4 * 40 + 6]
+            .copy_from_slice(&[0x12, 0x05, 0x01, 0x04, 0x19, 0x2e]);
+    }
+    ultimate.wait_ready(timeout).unwrap();
+
+    let port = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let offline = Rest::with_port(&Host::Ipv4(Ipv4Addr::LOCALHOST), port, None).unwrap();
+    assert!(matches!(
+        offline.wait_online(timeout),
+        Err(Error::Timeout(_))
+    ));
+}
+
+#[test]
+fn test_capabilities() {
+    let (server, ultimate) = connect();
+    {
+        let mut state = server.state();
+        state.info.product = "Ultimate-II+".to_string();
+        state.info.firmware_version = "3.11".to_string();
+    }
+    let capabilities = ultimate.capabilities().unwrap();
+    assert_eq!(capabilities.api_version, "0.1");
+    assert_eq!(capabilities.product, Some(Product::UltimateII));
+    assert!(ultimate.require(Feature::DebugRegister).is_err());
+    match ultimate.require(Feature::DriveRom) {
+        Err(Error::Incompatible(message)) => assert_eq!(
+            message,
+            "drive ROM upload requires firmware 3.12 or newer (connected: Ultimate-II with firmware 3.11)"
+        ),
+        other => panic!("unexpected result: {other:?}"),
+    }
+
+    // probed only once
+    server.state().info.firmware_version = "3.12".to_string();
+    assert!(ultimate.require(Feature::DriveRom).is_err());
+}
+
+#[test]
+fn test_discovery() {
+    let (server, _) = connect();
+    let scan = Scan {
+        port: server.port(),
+        password: Some(PASSWORD.into()),
+        ..Scan::new(vec!["127.0.0.1/32".parse().unwrap()])
+    };
+    let devices = scan.run().unwrap();
+    assert_eq!(devices.len(), 1);
+    assert_eq!(devices[0].0, IpAddr::V4(Ipv4Addr::LOCALHOST));
+    assert_eq!(devices[0].1.hostname, "mock-u64");
+    assert_eq!(scan.resolve("mock01").unwrap(), devices[0].0);
+    assert!(scan.resolve("other").is_err());
+}
+
+#[test]
+fn test_group() {
+    let (first, first_rest) = connect();
+    let (second, _) = connect();
+    let wrong_password =
+        Rest::with_port(&second.host(), second.port(), Some("wrong".into())).unwrap();
+    let group: RestGroup = [
+        ("first".to_string(), first_rest),
+        ("second".to_string(), wrong_password),
+    ]
+    .into_iter()
+    .collect();
+    assert_eq!(group.names().collect::<Vec<_>>(), ["first", "second"]);
+
+    let results = group.reset();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].0, "first");
+    assert!(results[0].1.is_ok());
+    assert!(matches!(results[1].1, Err(Error::Auth)));
+    assert!(first
+        .state()
+        .requests
+        .contains(&"PUT machine:reset".to_string()));
+
+    let hostnames = group.fan_out(|_, rest| rest.info().map(|info| info.hostname));
+    assert_eq!(hostnames[0].1.as_deref().unwrap(), "mock-u64");
+}
+
+#[test]
+fn test_snapshot() {
+    let (server, ultimate) = connect();
+    ultimate.write_mem(0x0400, b"SAVE").unwrap();
+    ultimate.write_mem(0xd020, &[0x02]).unwrap();
+    let snapshot = ultimate.snapshot().unwrap();
+    assert!(!server.state().paused);
+    assert_eq!(snapshot.ram[0x0400..0x0404], *b"SAVE");
+    assert_eq!(snapshot.vic[0x20], 0x02);
+
+    ultimate.write_mem(0x0400, b"GONE").unwrap();
+    ultimate.write_mem(0xd020, &[0x00]).unwrap();
+    ultimate.restore_snapshot(&snapshot).unwrap();
+    let state = server.state();
+    assert_eq!(state.ram[0x0400..0x0404], *b"SAVE");
+    assert_eq!(state.ram[0xd020], 0x02);
+    assert!(!state.paused);
+    assert!(state.requests.contains(&"PUT machine:pause".to_string()));
+}